@@ -9,16 +9,24 @@ use actix::Actor;
 use actix_cors::Cors;
 use actix_web::{error, middleware, web, App, HttpResponse, HttpServer};
 use dotenv::dotenv;
-use log::info;
+use log::{info, warn};
 use routes::{
+    admin::change_game_loop_state,
     auth::auth_login,
+    balance::reset_my_balance,
     create_ws::create_crash_game,
+    rooms::get_rooms,
+    rounds::{get_rounds, verify_round, verify_round_seeds},
     stats::get_stats,
+    transactions::get_my_transactions,
     utils::error_response::{AppError, AppErrorResponse},
 };
 use services::{
+    balance_store::{BalanceStore, BalanceStoreType, FileBalanceStore, InMemoryBalanceStore},
     balance_system::{self, BalanceSystem}, env_settings::EnvSettings, game_server::GameServer,
-    game_stats::GameStats,
+    game_stats::GameStats, ledger::Ledger, room::RoomRegistry, round_history::RoundHistory,
+    seed_chain::SeedChain,
+    wallet_provider::{HttpWalletProvider, WalletProvider},
 };
 
 #[actix_web::main]
@@ -31,12 +39,52 @@ async fn main() -> std::io::Result<()> {
     let port = env_settings.server_port;
 
     let game_stats = GameStats::new();
-    let balance_system = BalanceSystem::new();
+    let balance_store_type = env_settings.balance_store_type;
+    let (balance_store, ledger): (Arc<dyn BalanceStore>, Ledger) = match balance_store_type {
+        BalanceStoreType::Memory => (Arc::new(InMemoryBalanceStore::new()), Ledger::in_memory()),
+        BalanceStoreType::File => (
+            Arc::new(FileBalanceStore::open(&env_settings.balance_store_file)),
+            Ledger::open(&env_settings.ledger_file),
+        ),
+    };
+    let wallet: Option<Arc<dyn WalletProvider>> = if env_settings.wallet_url.is_empty() {
+        None
+    } else {
+        Some(Arc::new(HttpWalletProvider::new(
+            &env_settings.wallet_url,
+            &env_settings.wallet_api_key,
+            env_settings.wallet_timeout_ms,
+        )))
+    };
+    let balance_system = BalanceSystem::new(balance_store, ledger.clone(), wallet);
+    let ledger_mismatches = balance_system.verify_ledger();
+    if !ledger_mismatches.is_empty() {
+        warn!(
+            "{:?} balances don't match the transaction ledger!",
+            ledger_mismatches.len()
+        );
+    }
+    let round_history = if env_settings.round_history_file.is_empty() {
+        RoundHistory::new(env_settings.round_history_size)
+    } else {
+        RoundHistory::open(
+            &env_settings.round_history_file,
+            env_settings.round_history_size,
+        )
+    };
+    let seed_chain = SeedChain::load_or_create(
+        env_settings.seed_chain_length,
+        &env_settings.seed_chain_state_file,
+    );
+    let room_registry = RoomRegistry::new(env_settings.rooms.clone());
 
     let game_server = GameServer::new(
         game_stats.clone(),
         env_settings.clone(),
-        balance_system,
+        balance_system.clone(),
+        seed_chain,
+        round_history.clone(),
+        room_registry.clone(),
     )
     .start();
 
@@ -50,6 +98,10 @@ async fn main() -> std::io::Result<()> {
             .app_data(web::Data::new(env_settings.clone()))
             .app_data(web::Data::new(game_server.clone()))
             .app_data(web::Data::new(game_stats.clone()))
+            .app_data(web::Data::new(round_history.clone()))
+            .app_data(web::Data::new(ledger.clone()))
+            .app_data(web::Data::new(balance_system.clone()))
+            .app_data(web::Data::new(room_registry.clone()))
             .app_data(
                 web::JsonConfig::default()
                     .limit(1024)
@@ -62,7 +114,18 @@ async fn main() -> std::io::Result<()> {
                         .into();
                     }),
             )
-            .service(web::scope("/api").service(get_stats).service(auth_login))
+            .service(
+                web::scope("/api")
+                    .service(get_stats)
+                    .service(get_rooms)
+                    .service(auth_login)
+                    .service(get_my_transactions)
+                    .service(reset_my_balance)
+                    .service(get_rounds)
+                    .service(verify_round)
+                    .service(verify_round_seeds)
+                    .service(change_game_loop_state),
+            )
             .service(web::scope("/ws").service(create_crash_game))
     })
     .bind(("0.0.0.0", port))?
@@ -0,0 +1,95 @@
+use std::sync::atomic::Ordering;
+
+use actix::Addr;
+use actix_web::{http::StatusCode, post, web, HttpRequest, HttpResponse, Responder, ResponseError};
+use derive_more::Display;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::services::{
+    env_settings::EnvSettings,
+    game_server::GameServer,
+    game_stats::{GameLoopState, GameStats},
+    message_types::ChangeGameLoopState,
+};
+
+use super::utils::error_response::AppErrorResponse;
+
+#[derive(Serialize, Debug, Display)]
+pub enum AdminError {
+    InvalidApiKey = 10041,
+    GenericError,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GameLoopRequestData {
+    state: GameLoopState,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GameLoopResponseData {
+    game_loop_state: GameLoopState,
+    /// rooms, whose round is not finished yet
+    active_rounds: u32,
+}
+
+impl ResponseError for AdminError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AdminError::InvalidApiKey => StatusCode::UNAUTHORIZED,
+            AdminError::GenericError => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+
+        match self {
+            AdminError::InvalidApiKey => {
+                HttpResponse::build(status).json(AppErrorResponse::from(AdminError::InvalidApiKey))
+            }
+            AdminError::GenericError => {
+                HttpResponse::build(status).json(AppErrorResponse::from(AdminError::GenericError))
+            }
+        }
+    }
+}
+
+/// Pauses (`paused`), drains (`draining`) or resumes (`running`) the game loops of all rooms,
+/// the server is safe to stop when there are no active rounds left
+#[post("/admin/game-loop")]
+pub async fn change_game_loop_state(
+    req: HttpRequest,
+    param_obj: web::Json<GameLoopRequestData>,
+    env_settings: web::Data<EnvSettings>,
+    game_server_addr: web::Data<Addr<GameServer>>,
+    game_stats: web::Data<GameStats>,
+) -> Result<impl Responder, AdminError> {
+    let api_key = req
+        .headers()
+        .get("X-Api-Key")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("");
+    // admin routes are disabled without an api key
+    if env_settings.admin_api_key.is_empty() || api_key != env_settings.admin_api_key {
+        return Err(AdminError::InvalidApiKey);
+    }
+
+    info!("admin changes the game loop state to {:?}", param_obj.state);
+    if let Err(err) = game_server_addr
+        .send(ChangeGameLoopState {
+            state: param_obj.state,
+        })
+        .await
+    {
+        warn!("unable to change the game loop state! {:?}", err);
+        return Err(AdminError::GenericError);
+    }
+
+    Ok(web::Json(GameLoopResponseData {
+        game_loop_state: game_stats.game_loop_state(),
+        active_rounds: game_stats.active_rounds.load(Ordering::SeqCst),
+    }))
+}
@@ -4,7 +4,10 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     routes::utils::auth_token_extractor::UserAuthentication,
-    services::env_settings::EnvSettings,
+    services::{
+        balance_system::{BalanceError, BalanceSystem},
+        env_settings::EnvSettings,
+    },
 };
 
 use super::utils::error_response::AppErrorResponse;
@@ -13,10 +16,13 @@ use super::utils::error_response::AppErrorResponse;
 pub enum LoginError {
     GenericError = 10011,
     InvalidEmailOrPassword,
+    InvalidWalletToken,
+    WalletUnavailable,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum PlayMode {
+    #[default]
     FUN = 0,
     REAL,
 }
@@ -27,6 +33,8 @@ struct LoginRequestData {
     email: String,
     password: String,
     play_mode: PlayMode,
+    /// session token given by the operator, required in REAL mode
+    wallet_token: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -42,6 +50,8 @@ impl ResponseError for LoginError {
         match self {
             LoginError::GenericError => StatusCode::INTERNAL_SERVER_ERROR,
             LoginError::InvalidEmailOrPassword => StatusCode::BAD_REQUEST,
+            LoginError::InvalidWalletToken => StatusCode::UNAUTHORIZED,
+            LoginError::WalletUnavailable => StatusCode::SERVICE_UNAVAILABLE,
         }
     }
 
@@ -54,6 +64,10 @@ impl ResponseError for LoginError {
             }
             LoginError::InvalidEmailOrPassword => HttpResponse::build(status)
                 .json(AppErrorResponse::from(LoginError::InvalidEmailOrPassword)),
+            LoginError::InvalidWalletToken => HttpResponse::build(status)
+                .json(AppErrorResponse::from(LoginError::InvalidWalletToken)),
+            LoginError::WalletUnavailable => HttpResponse::build(status)
+                .json(AppErrorResponse::from(LoginError::WalletUnavailable)),
         }
     }
 }
@@ -62,9 +76,11 @@ impl ResponseError for LoginError {
 async fn auth_login(
     param_obj: web::Json<LoginRequestData>,
     env_settings: web::Data<EnvSettings>,
+    balance_system: web::Data<BalanceSystem>,
 ) -> Result<impl Responder, LoginError> {
     let payload = param_obj.into_inner();
-    log::info!("/auth {:?}", payload);
+    // credentials and the wallet token are not logged
+    log::info!("/auth {:?}", payload.play_mode);
 
     match payload.play_mode {
         PlayMode::FUN => {
@@ -79,7 +95,28 @@ async fn auth_login(
 
             Ok(web::Json(response_data))
         }
-        // todo: real mode
-        PlayMode::REAL => Err(LoginError::GenericError),
+        PlayMode::REAL => {
+            let wallet_token = payload.wallet_token.ok_or(LoginError::InvalidWalletToken)?;
+            // the wallet is called on the blocking thread pool
+            let player_id = web::block(move || balance_system.authenticate_wallet(&wallet_token))
+                .await
+                .map_err(|_| LoginError::GenericError)?
+                .map_err(|err| match err {
+                    BalanceError::UnknownPlayer => LoginError::InvalidWalletToken,
+                    _ => LoginError::WalletUnavailable,
+                })?;
+
+            let real_auth =
+                UserAuthentication::create_auth(&env_settings, player_id, PlayMode::REAL)
+                    .map_err(|_| LoginError::GenericError)?;
+
+            let response_data = LoginSuccessResponse {
+                jwt_token: real_auth.authentication_token,
+                uuid: real_auth.uuid.clone(),
+                display_name: "".to_string(),
+            };
+
+            Ok(web::Json(response_data))
+        }
     }
 }
@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use actix_web::{http::StatusCode, post, web, HttpResponse, Responder, ResponseError};
+use derive_more::Display;
+use log::warn;
+use serde::Serialize;
+
+use crate::{
+    routes::{auth::PlayMode, utils::auth_token_extractor::UserAuthentication},
+    services::{
+        balance_system::{BalanceError, BalanceSystem},
+        env_settings::EnvSettings,
+    },
+};
+
+use super::utils::error_response::AppErrorResponse;
+
+#[derive(Serialize, Debug, Display)]
+pub enum BalanceResetError {
+    RealModeNotAllowed = 10031,
+    CooldownNotPassed,
+    GenericError,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BalanceResetResponseData {
+    balance: u64,
+    currency: String,
+}
+
+impl ResponseError for BalanceResetError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            BalanceResetError::RealModeNotAllowed => StatusCode::FORBIDDEN,
+            BalanceResetError::CooldownNotPassed => StatusCode::TOO_MANY_REQUESTS,
+            BalanceResetError::GenericError => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+
+        match self {
+            BalanceResetError::RealModeNotAllowed => HttpResponse::build(status).json(
+                AppErrorResponse::from(BalanceResetError::RealModeNotAllowed),
+            ),
+            BalanceResetError::CooldownNotPassed => HttpResponse::build(status)
+                .json(AppErrorResponse::from(BalanceResetError::CooldownNotPassed)),
+            BalanceResetError::GenericError => HttpResponse::build(status)
+                .json(AppErrorResponse::from(BalanceResetError::GenericError)),
+        }
+    }
+}
+
+/// Tops up the FUN mode balance of the authenticated player
+#[post("/me/balance/reset")]
+pub async fn reset_my_balance(
+    user_auth: UserAuthentication,
+    env_settings: web::Data<EnvSettings>,
+    balance_system: web::Data<BalanceSystem>,
+) -> Result<impl Responder, BalanceResetError> {
+    if user_auth.play_mode != PlayMode::FUN {
+        return Err(BalanceResetError::RealModeNotAllowed);
+    }
+
+    let currency = env_settings.currency_of(PlayMode::FUN).to_string();
+    let uuid = user_auth.uuid.clone();
+    let refill_currency = currency.clone();
+    let refill_amount = env_settings.fun_refill_amount;
+    let cooldown = Duration::from_secs(env_settings.fun_refill_cooldown_secs);
+    // balance store is read and written on the blocking thread pool
+    let result = web::block(move || {
+        balance_system
+            .ensure_balance(uuid.clone(), PlayMode::FUN, &refill_currency)
+            .and_then(|_| balance_system.refill(&uuid, refill_amount, cooldown))
+    })
+    .await
+    .map_err(|_| BalanceResetError::GenericError)?;
+
+    match result {
+        Ok(balance) => Ok(web::Json(BalanceResetResponseData { balance, currency })),
+        Err(BalanceError::RefillNotAllowed) => Err(BalanceResetError::RealModeNotAllowed),
+        Err(BalanceError::RefillCooldown) => Err(BalanceResetError::CooldownNotPassed),
+        Err(err) => {
+            warn!("unable to reset balance of {:?}! {:?}", user_auth.uuid, err);
+            Err(BalanceResetError::GenericError)
+        }
+    }
+}
@@ -1,4 +1,9 @@
+pub mod admin;
 pub mod auth;
+pub mod balance;
 pub mod create_ws;
+pub mod rooms;
+pub mod rounds;
 pub mod stats;
+pub mod transactions;
 pub mod utils;
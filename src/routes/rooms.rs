@@ -0,0 +1,44 @@
+use actix_web::{get, web, Responder};
+use serde::Serialize;
+
+use crate::services::room::RoomRegistry;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RoomData {
+    room_id: String,
+    currency: String,
+    /// in seconds
+    betting_time_duration: u32,
+    house_edge: f32,
+    min_bet: u64,
+    max_bet: u64,
+    max_win: u64,
+    players_online: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RoomsResponseData {
+    rooms: Vec<RoomData>,
+}
+
+/// Rooms players can join, with the number of players in them
+#[get("/rooms")]
+pub async fn get_rooms(room_registry: web::Data<RoomRegistry>) -> impl Responder {
+    let rooms = room_registry
+        .list()
+        .into_iter()
+        .map(|(settings, players_online)| RoomData {
+            room_id: settings.id,
+            currency: settings.currency,
+            betting_time_duration: settings.betting_time_duration,
+            house_edge: settings.house_edge_pct,
+            min_bet: settings.bet_limits.min_bet,
+            max_bet: settings.bet_limits.max_bet,
+            max_win: settings.bet_limits.max_win,
+            players_online,
+        })
+        .collect();
+    web::Json(RoomsResponseData { rooms })
+}
@@ -0,0 +1,256 @@
+use actix_web::{get, http::StatusCode, post, web, HttpResponse, Responder, ResponseError};
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+use crate::services::{
+    crash_game_math::{sha256, CrashGameMath},
+    round_history::{RoundHistory, RoundRecord},
+};
+
+use super::utils::error_response::AppErrorResponse;
+
+const MAX_PAGE_SIZE: usize = 100;
+
+#[derive(Serialize, Debug, Display)]
+pub enum RoundsError {
+    RoundNotFound = 10021,
+    InvalidHouseEdge,
+    VerificationFailed,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct RoundsQuery {
+    /// starting from 1
+    page: Option<usize>,
+    page_size: Option<usize>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RoundData {
+    round_id: u32,
+    room_id: String,
+    started_at: String,
+    ended_at: String,
+    crash_point: f64,
+    server_seed_hash: String,
+    seed_chain_hash: String,
+    server_seed: String,
+    client_seed: String,
+    house_edge: f32,
+    total_wagered: u64,
+    total_paid_out: u64,
+    player_count: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RoundsResponseData {
+    rounds: Vec<RoundData>,
+    page: usize,
+    page_size: usize,
+    total_rounds: usize,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct VerifyRequestData {
+    server_seed: String,
+    client_seed: String,
+    round_id: u32,
+    /// value between 0 to 1
+    house_edge: f32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct VerifyResponseData {
+    round_id: u32,
+    /// HMAC-SHA256 the crash point is derived from
+    hash: String,
+    crash_point: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RoundVerificationResponseData {
+    round_id: u32,
+    server_seed: String,
+    client_seed: String,
+    client_seed_inputs: Vec<String>,
+    client_seed_salt: String,
+    house_edge: f32,
+    hash: String,
+    crash_point: f64,
+    recorded_crash_point: f64,
+    /// client seed is derived from the client seed inputs and the salt
+    client_seed_verified: bool,
+    /// server seed matches the hash published before the round
+    server_seed_hash_verified: bool,
+    /// sha256 of the server seed is the seed of the previous round, or the seed chain hash.
+    /// null, if the previous round is not in the history anymore
+    chain_link_verified: Option<bool>,
+    verified: bool,
+}
+
+impl ResponseError for RoundsError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            RoundsError::RoundNotFound => StatusCode::NOT_FOUND,
+            RoundsError::InvalidHouseEdge => StatusCode::BAD_REQUEST,
+            RoundsError::VerificationFailed => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+
+        match self {
+            RoundsError::RoundNotFound => {
+                HttpResponse::build(status).json(AppErrorResponse::from(RoundsError::RoundNotFound))
+            }
+            RoundsError::InvalidHouseEdge => HttpResponse::build(status)
+                .json(AppErrorResponse::from(RoundsError::InvalidHouseEdge)),
+            RoundsError::VerificationFailed => HttpResponse::build(status)
+                .json(AppErrorResponse::from(RoundsError::VerificationFailed)),
+        }
+    }
+}
+
+impl From<RoundRecord> for RoundData {
+    fn from(round: RoundRecord) -> RoundData {
+        RoundData {
+            round_id: round.round_id,
+            room_id: round.room_id,
+            started_at: round.started_at.to_rfc3339(),
+            ended_at: round.ended_at.to_rfc3339(),
+            crash_point: round.crash_point as f64 / 100.0,
+            server_seed_hash: round.server_seed_hash,
+            seed_chain_hash: round.seed_chain_hash,
+            server_seed: round.server_seed,
+            client_seed: round.client_seed,
+            house_edge: round.house_edge_pct,
+            total_wagered: round.total_wagered,
+            total_paid_out: round.total_paid_out,
+            player_count: round.player_count,
+        }
+    }
+}
+
+/// Runs the crash point math for the given seeds, returns the HMAC hash and the crash point
+fn compute_crash_point(
+    server_seed: &str,
+    client_seed: &str,
+    house_edge_pct: f32,
+    round_id: u32,
+) -> Result<(String, f64), RoundsError> {
+    if !(house_edge_pct > 0.0 && house_edge_pct < 1.0) {
+        return Err(RoundsError::InvalidHouseEdge);
+    }
+
+    let hash = CrashGameMath::generate_round_hex_hash(server_seed, client_seed, &round_id);
+    let crash_point =
+        CrashGameMath::generate_crash_point(server_seed, client_seed, &house_edge_pct, &round_id)
+            .ok_or(RoundsError::VerificationFailed)?;
+
+    Ok((hash, crash_point))
+}
+
+/// Finished rounds, newest first
+#[get("/rounds")]
+pub async fn get_rounds(
+    query: web::Query<RoundsQuery>,
+    round_history: web::Data<RoundHistory>,
+) -> impl Responder {
+    let page = query.page.unwrap_or(1).max(1);
+    let page_size = query.page_size.unwrap_or(20).clamp(1, MAX_PAGE_SIZE);
+
+    let (rounds, total_rounds) = round_history.list(page, page_size);
+
+    let response_data = RoundsResponseData {
+        rounds: rounds.into_iter().map(RoundData::from).collect(),
+        page,
+        page_size,
+        total_rounds,
+    };
+    web::Json(response_data)
+}
+
+#[get("/rounds/{round_id}/verify")]
+pub async fn verify_round(
+    path: web::Path<u32>,
+    round_history: web::Data<RoundHistory>,
+) -> Result<impl Responder, RoundsError> {
+    let round_id = path.into_inner();
+    let round = round_history
+        .find(round_id)
+        .ok_or(RoundsError::RoundNotFound)?;
+
+    let (hash, crash_point) = compute_crash_point(
+        &round.server_seed,
+        &round.client_seed,
+        round.house_edge_pct,
+        round.round_id,
+    )?;
+    let recorded_crash_point = round.crash_point as f64 / 100.0;
+
+    let client_seed_verified =
+        CrashGameMath::derive_client_seed(&round.client_seed_inputs, &round.client_seed_salt)
+            == round.client_seed;
+    let server_seed_hash_verified = sha256(&round.server_seed) == round.server_seed_hash;
+    // seeds are consumed backwards, the hash of the seed is the seed of the previous round
+    let previous_seed = sha256(&round.server_seed);
+    let chain_link_verified = if previous_seed == round.seed_chain_hash {
+        Some(true)
+    } else {
+        round_history
+            .find(round.round_id.saturating_sub(1))
+            .filter(|previous_round| previous_round.seed_chain_hash == round.seed_chain_hash)
+            .map(|previous_round| previous_round.server_seed == previous_seed)
+    };
+    let verified = (crash_point * 100.0).round() as u32 == round.crash_point
+        && client_seed_verified
+        && server_seed_hash_verified
+        && chain_link_verified != Some(false);
+
+    let response_data = RoundVerificationResponseData {
+        round_id: round.round_id,
+        server_seed: round.server_seed,
+        client_seed: round.client_seed,
+        client_seed_inputs: round.client_seed_inputs,
+        client_seed_salt: round.client_seed_salt,
+        house_edge: round.house_edge_pct,
+        hash,
+        crash_point,
+        recorded_crash_point,
+        client_seed_verified,
+        server_seed_hash_verified,
+        chain_link_verified,
+        verified,
+    };
+
+    Ok(web::Json(response_data))
+}
+
+#[post("/verify")]
+pub async fn verify_round_seeds(
+    param_obj: web::Json<VerifyRequestData>,
+) -> Result<impl Responder, RoundsError> {
+    let payload = param_obj.into_inner();
+
+    let (hash, crash_point) = compute_crash_point(
+        &payload.server_seed,
+        &payload.client_seed,
+        payload.house_edge,
+        payload.round_id,
+    )?;
+
+    let response_data = VerifyResponseData {
+        round_id: payload.round_id,
+        hash,
+        crash_point,
+    };
+
+    Ok(web::Json(response_data))
+}
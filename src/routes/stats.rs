@@ -3,17 +3,24 @@ use std::sync::atomic::Ordering;
 use actix_web::{get, web, Responder};
 use serde::Serialize;
 
-use crate::services::game_stats::GameStats;
+use crate::services::game_stats::{GameLoopState, GameStats};
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct StatsResponseData {
     players_online: u32,
+    game_loop_state: GameLoopState,
+    /// rooms, whose round is not finished yet
+    active_rounds: u32,
 }
 
 #[get("/stats")]
 pub async fn get_stats(game_stats: web::Data<GameStats>) -> impl Responder {
     let players_online = game_stats.players_online.load(Ordering::SeqCst);
-    let response_data = StatsResponseData { players_online };
+    let response_data = StatsResponseData {
+        players_online,
+        game_loop_state: game_stats.game_loop_state(),
+        active_rounds: game_stats.active_rounds.load(Ordering::SeqCst),
+    };
     return web::Json(response_data);
 }
@@ -0,0 +1,33 @@
+use actix_web::{get, web, Responder};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    routes::utils::auth_token_extractor::UserAuthentication,
+    services::ledger::{Ledger, LedgerEntry, MAX_PLAYER_ENTRIES},
+};
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct TransactionsQuery {
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TransactionsResponseData {
+    transactions: Vec<LedgerEntry>,
+}
+
+/// Ledger entries of the authenticated player, newest first
+#[get("/me/transactions")]
+pub async fn get_my_transactions(
+    user_auth: UserAuthentication,
+    query: web::Query<TransactionsQuery>,
+    ledger: web::Data<Ledger>,
+) -> impl Responder {
+    let limit = query.limit.unwrap_or(50).clamp(1, MAX_PLAYER_ENTRIES);
+    let response_data = TransactionsResponseData {
+        transactions: ledger.entries_of(&user_auth.uuid, limit),
+    };
+    web::Json(response_data)
+}
@@ -11,21 +11,24 @@ use serde::{Deserialize, Serialize};
 use std::future::{ready, Ready};
 use uuid::Uuid;
 
-use crate::services::env_settings::EnvSettings;
+use crate::{routes::auth::PlayMode, services::env_settings::EnvSettings};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct UserClaims {
     pub exp: usize,
     pub uuid: String,
+    #[serde(default)]
+    pub play_mode: PlayMode,
 }
 
 impl UserClaims {
-    pub fn new(user_jwt_expiration_minutes: i64, uuid: String) -> Self {
+    pub fn new(user_jwt_expiration_minutes: i64, uuid: String, play_mode: PlayMode) -> Self {
         let token_expiry_date =
             (Utc::now() + Duration::minutes(user_jwt_expiration_minutes)).timestamp() as usize;
         Self {
             exp: token_expiry_date,
             uuid: uuid,
+            play_mode,
         }
     }
 }
@@ -34,6 +37,7 @@ impl UserClaims {
 pub struct UserAuthentication {
     pub authentication_token: String,
     pub uuid: String,
+    pub play_mode: PlayMode,
 }
 
 impl UserAuthentication {
@@ -55,6 +59,7 @@ impl UserAuthentication {
                     Ok(UserAuthentication {
                         authentication_token: jwt_token.to_owned(),
                         uuid: user_uuid,
+                        play_mode: user_claims.play_mode,
                     })
                 } else {
                     Err(())
@@ -66,9 +71,19 @@ impl UserAuthentication {
 
     pub fn create_guest_auth(env_settings: &EnvSettings) -> Result<UserAuthentication, ()> {
         let uuid = Uuid::new_v4();
-        let uuid_str = uuid.to_string();
+        UserAuthentication::create_auth(env_settings, uuid.to_string(), PlayMode::FUN)
+    }
 
-        let claims = UserClaims::new(env_settings.user_jwt_expiration_minutes, uuid_str.clone());
+    pub fn create_auth(
+        env_settings: &EnvSettings,
+        uuid_str: String,
+        play_mode: PlayMode,
+    ) -> Result<UserAuthentication, ()> {
+        let claims = UserClaims::new(
+            env_settings.user_jwt_expiration_minutes,
+            uuid_str.clone(),
+            play_mode,
+        );
 
         let jwt_token_result = encode(
             &Header::default(),
@@ -80,6 +95,7 @@ impl UserAuthentication {
             Ok(jwt_token) => Ok(UserAuthentication {
                 authentication_token: jwt_token,
                 uuid: uuid_str,
+                play_mode,
             }),
             Err(_) => Err(()),
         }
@@ -123,6 +139,7 @@ impl FromRequest for UserAuthentication {
                 ready(Ok(UserAuthentication {
                     authentication_token,
                     uuid: user_claims.uuid,
+                    play_mode: user_claims.play_mode,
                 }))
             }
             Err(_) => {
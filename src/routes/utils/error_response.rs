@@ -1,7 +1,9 @@
 use derive_more::Display;
 use serde::Serialize;
 
-use crate::routes::auth::LoginError;
+use crate::routes::{
+    admin::AdminError, auth::LoginError, balance::BalanceResetError, rounds::RoundsError,
+};
 
 #[derive(Serialize, Debug, Display)]
 pub enum AppError {
@@ -43,6 +45,87 @@ impl From<LoginError> for AppErrorResponse {
                     error_message: "Invalid email or password".to_string(),
                 };
             }
+            LoginError::InvalidWalletToken => {
+                return AppErrorResponse {
+                    error_code: LoginError::InvalidWalletToken as u16,
+                    error_message: "Invalid wallet token".to_string(),
+                };
+            }
+            LoginError::WalletUnavailable => {
+                return AppErrorResponse {
+                    error_code: LoginError::WalletUnavailable as u16,
+                    error_message: "Wallet is not available".to_string(),
+                };
+            }
+        }
+    }
+}
+
+impl From<RoundsError> for AppErrorResponse {
+    fn from(value: RoundsError) -> AppErrorResponse {
+        match value {
+            RoundsError::RoundNotFound => {
+                return AppErrorResponse {
+                    error_code: RoundsError::RoundNotFound as u16,
+                    error_message: "Round not found".to_string(),
+                };
+            }
+            RoundsError::InvalidHouseEdge => {
+                return AppErrorResponse {
+                    error_code: RoundsError::InvalidHouseEdge as u16,
+                    error_message: "House edge must be between 0 and 1".to_string(),
+                };
+            }
+            RoundsError::VerificationFailed => {
+                return AppErrorResponse {
+                    error_code: RoundsError::VerificationFailed as u16,
+                    error_message: "Unable to compute crash point".to_string(),
+                };
+            }
+        }
+    }
+}
+
+impl From<BalanceResetError> for AppErrorResponse {
+    fn from(value: BalanceResetError) -> AppErrorResponse {
+        match value {
+            BalanceResetError::RealModeNotAllowed => {
+                return AppErrorResponse {
+                    error_code: BalanceResetError::RealModeNotAllowed as u16,
+                    error_message: "Balance can only be reset in FUN mode".to_string(),
+                };
+            }
+            BalanceResetError::CooldownNotPassed => {
+                return AppErrorResponse {
+                    error_code: BalanceResetError::CooldownNotPassed as u16,
+                    error_message: "Balance was reset recently, try again later".to_string(),
+                };
+            }
+            BalanceResetError::GenericError => {
+                return AppErrorResponse {
+                    error_code: BalanceResetError::GenericError as u16,
+                    error_message: "Something went wrong".to_string(),
+                };
+            }
+        }
+    }
+}
+
+impl From<AdminError> for AppErrorResponse {
+    fn from(value: AdminError) -> AppErrorResponse {
+        match value {
+            AdminError::InvalidApiKey => {
+                return AppErrorResponse {
+                    error_code: AdminError::InvalidApiKey as u16,
+                    error_message: "Invalid admin api key".to_string(),
+                };
+            }
+            AdminError::GenericError => {
+                return AppErrorResponse {
+                    error_code: AdminError::GenericError as u16,
+                    error_message: "Something went wrong".to_string(),
+                };
+            }
         }
     }
 }
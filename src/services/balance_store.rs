@@ -0,0 +1,257 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::Path,
+    str::FromStr,
+    sync::Mutex,
+};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::currency::default_currency;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BalanceStoreType {
+    /// balances are lost on restart
+    Memory,
+    /// balances are appended to a file, and restored on restart
+    File,
+}
+
+impl FromStr for BalanceStoreType {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "memory" => Ok(BalanceStoreType::Memory),
+            "file" => Ok(BalanceStoreType::File),
+            _ => Err(format!("unknown balance store type {:?}", value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredBalance {
+    pub balance: u64,
+    /// amount reserved for a bet, that is not committed yet
+    pub reserved: u64,
+}
+
+/// (uuid, currency) of a balance
+type BalanceKey = (String, String);
+
+/// Storage of player balances, `BalanceSystem` writes every change through to the store.
+/// A player has a separate balance in every currency.
+pub trait BalanceStore: Debug + Send + Sync {
+    fn load(&self, uuid: &str, currency: &str) -> Option<StoredBalance>;
+
+    fn save(&self, uuid: &str, currency: &str, balance: StoredBalance);
+}
+
+#[derive(Debug, Default)]
+pub struct InMemoryBalanceStore {
+    balances: Mutex<HashMap<BalanceKey, StoredBalance>>,
+}
+
+impl InMemoryBalanceStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl BalanceStore for InMemoryBalanceStore {
+    fn load(&self, uuid: &str, currency: &str) -> Option<StoredBalance> {
+        let key = (uuid.to_string(), currency.to_string());
+        self.balances.lock().unwrap().get(&key).copied()
+    }
+
+    fn save(&self, uuid: &str, currency: &str, balance: StoredBalance) {
+        let key = (uuid.to_string(), currency.to_string());
+        self.balances.lock().unwrap().insert(key, balance);
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BalanceLogEntry {
+    uuid: String,
+    #[serde(default = "default_currency")]
+    currency: String,
+    #[serde(flatten)]
+    balance: StoredBalance,
+}
+
+/// Append-only log of balance changes, one JSON object per line. The last line of a balance wins.
+/// The log is compacted to a single line per balance, when the store is opened.
+#[derive(Debug)]
+pub struct FileBalanceStore {
+    balances: Mutex<HashMap<BalanceKey, StoredBalance>>,
+    file: Mutex<File>,
+}
+
+impl FileBalanceStore {
+    pub fn open(file_path: &str) -> Self {
+        let mut balances = HashMap::new();
+        if let Ok(data) = fs::read_to_string(file_path) {
+            for line in data.lines().filter(|line| !line.trim().is_empty()) {
+                match serde_json::from_str::<BalanceLogEntry>(line) {
+                    Ok(entry) => {
+                        balances.insert((entry.uuid, entry.currency), entry.balance);
+                    }
+                    // a half written line, when the server was killed while writing
+                    Err(err) => warn!("skipping invalid balance log entry! {:?}", err),
+                }
+            }
+            info!(
+                "restored {:?} balances from {:?}",
+                balances.len(),
+                file_path
+            );
+        }
+
+        if let Some(dir) = Path::new(file_path).parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        FileBalanceStore::compact(file_path, &balances);
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(file_path)
+            .expect("balance store file must be writable");
+
+        Self {
+            balances: Mutex::new(balances),
+            file: Mutex::new(file),
+        }
+    }
+
+    fn compact(file_path: &str, balances: &HashMap<BalanceKey, StoredBalance>) {
+        let data: String = balances
+            .iter()
+            .map(|((uuid, currency), balance)| {
+                let entry = BalanceLogEntry {
+                    uuid: uuid.clone(),
+                    currency: currency.clone(),
+                    balance: *balance,
+                };
+                serde_json::to_string(&entry).expect("balance log entry is serializable") + "\n"
+            })
+            .collect();
+
+        // write to a temp file first, so the log is never half written
+        let tmp_file_path = format!("{}.tmp", file_path);
+        let result =
+            fs::write(&tmp_file_path, data).and_then(|_| fs::rename(&tmp_file_path, file_path));
+        if let Err(err) = result {
+            warn!("unable to compact balance store! {:?}", err);
+        }
+    }
+}
+
+impl BalanceStore for FileBalanceStore {
+    fn load(&self, uuid: &str, currency: &str) -> Option<StoredBalance> {
+        let key = (uuid.to_string(), currency.to_string());
+        self.balances.lock().unwrap().get(&key).copied()
+    }
+
+    fn save(&self, uuid: &str, currency: &str, balance: StoredBalance) {
+        let key = (uuid.to_string(), currency.to_string());
+        self.balances.lock().unwrap().insert(key, balance);
+
+        let entry = BalanceLogEntry {
+            uuid: uuid.to_string(),
+            currency: currency.to_string(),
+            balance,
+        };
+        let line = serde_json::to_string(&entry).expect("balance log entry is serializable");
+        let mut file = self.file.lock().unwrap();
+        if let Err(err) = writeln!(file, "{}", line) {
+            warn!("unable to save balance of {:?}! {:?}", uuid, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::services::currency::DEFAULT_CURRENCY;
+
+    #[test]
+    fn test_file_store_restores_last_balance() {
+        let path = env::temp_dir().join(format!(
+            "crash-server-balance-store-{}.jsonl",
+            std::process::id()
+        ));
+        let file_path = path.to_string_lossy().to_string();
+        let _ = fs::remove_file(&file_path);
+
+        // balance saved before balances had a currency
+        fs::write(
+            &file_path,
+            "{\"uuid\":\"c\",\"balance\":7,\"reserved\":0}\n",
+        )
+        .unwrap();
+
+        let store = FileBalanceStore::open(&file_path);
+        store.save(
+            "a",
+            "EUR",
+            StoredBalance {
+                balance: 100,
+                reserved: 10,
+            },
+        );
+        store.save(
+            "a",
+            "EUR",
+            StoredBalance {
+                balance: 90,
+                reserved: 0,
+            },
+        );
+        store.save(
+            "a",
+            "USD",
+            StoredBalance {
+                balance: 40,
+                reserved: 0,
+            },
+        );
+        store.save(
+            "b",
+            "EUR",
+            StoredBalance {
+                balance: 5,
+                reserved: 0,
+            },
+        );
+        drop(store);
+
+        let store = FileBalanceStore::open(&file_path);
+        assert_eq!(
+            store.load("a", "EUR"),
+            Some(StoredBalance {
+                balance: 90,
+                reserved: 0
+            })
+        );
+        assert_eq!(store.load("a", "USD").map(|b| b.balance), Some(40));
+        assert_eq!(store.load("b", "EUR").map(|b| b.balance), Some(5));
+        assert_eq!(store.load("b", "USD"), None);
+        assert_eq!(
+            store.load("c", DEFAULT_CURRENCY).map(|b| b.balance),
+            Some(7)
+        );
+
+        // compacted to one line per balance
+        assert_eq!(fs::read_to_string(&file_path).unwrap().lines().count(), 4);
+
+        let _ = fs::remove_file(&file_path);
+    }
+}
@@ -1,116 +1,1222 @@
 use std::{
-    collections::HashMap,
-    sync::{atomic::{AtomicU64, Ordering}, Arc, RwLock},
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
-use log::info;
+use derive_more::Display;
+use log::{info, warn};
+
+use crate::routes::auth::PlayMode;
+
+use super::{
+    balance_store::{BalanceStore, StoredBalance},
+    ledger::{Ledger, LedgerEntryKind},
+    wallet_provider::{WalletError, WalletProvider},
+};
 
 const DEFAULT_GUEST_BALANCE: u64 = 999_900;
+/// number of transaction results kept to answer replayed transactions
+const MAX_COMPLETED_TRANSACTIONS: usize = 100_000;
+/// number of times a wallet request is repeated, when the wallet can't be reached
+const WALLET_RETRIES: usize = 2;
+
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum BalanceError {
+    UnknownPlayer,
+    InsufficientFunds,
+    Overflow,
+    /// wallet of a REAL mode player can't be reached, or there is no wallet configured
+    WalletUnavailable,
+    /// only balances of FUN mode players can be refilled
+    RefillNotAllowed,
+    /// balance was refilled recently
+    RefillCooldown,
+}
+
+impl From<WalletError> for BalanceError {
+    fn from(value: WalletError) -> BalanceError {
+        match value {
+            WalletError::InvalidToken | WalletError::UnknownPlayer => BalanceError::UnknownPlayer,
+            WalletError::InsufficientFunds => BalanceError::InsufficientFunds,
+            WalletError::Unavailable => BalanceError::WalletUnavailable,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionKind {
+    Bet,
+    Win,
+    Refund,
+}
+
+/// Deterministic transaction id of a balance change in a round, e.g. `32315:player-1:bet`.
+/// Repeating an operation with the same transaction id returns the original result,
+/// instead of applying it again.
+pub fn transaction_id(round_id: u32, uuid: &str, kind: TransactionKind) -> String {
+    let kind = match kind {
+        TransactionKind::Bet => "bet",
+        TransactionKind::Win => "win",
+        TransactionKind::Refund => "refund",
+    };
+    format!("{}:{}:{}", round_id, uuid, kind)
+}
+
+/// Results of completed transactions, oldest results are forgotten first.
+#[derive(Debug, Default)]
+struct CompletedTransactions {
+    results: HashMap<String, u64>,
+    order: VecDeque<String>,
+}
+
+impl CompletedTransactions {
+    fn get(&self, tx_id: &str) -> Option<u64> {
+        self.results.get(tx_id).copied()
+    }
+
+    fn insert(&mut self, tx_id: &str, result: u64) {
+        if self.results.insert(tx_id.to_string(), result).is_some() {
+            return;
+        }
+        self.order.push_back(tx_id.to_string());
+        if self.order.len() > MAX_COMPLETED_TRANSACTIONS {
+            if let Some(oldest_tx_id) = self.order.pop_front() {
+                self.results.remove(&oldest_tx_id);
+            }
+        }
+    }
+}
+
+/// (uuid, currency) of an account
+type AccountKey = (String, String);
+
+#[derive(Debug, Default)]
+struct Accounts {
+    /// FUN mode players, balance is kept in the store
+    local: HashMap<AccountKey, StoredBalance>,
+    /// REAL mode players, balance is held by the wallet and only cached here
+    wallet: HashMap<AccountKey, StoredBalance>,
+    /// currency the player currently plays with
+    currencies: HashMap<String, String>,
+    completed: CompletedTransactions,
+    /// time of the last refill of FUN mode accounts
+    refilled_at: HashMap<AccountKey, Instant>,
+}
+
+impl Accounts {
+    /// Key of the account the player currently plays with
+    fn key(&self, uuid: &str) -> Result<AccountKey, BalanceError> {
+        let currency = self
+            .currencies
+            .get(uuid)
+            .ok_or(BalanceError::UnknownPlayer)?;
+        Ok((uuid.to_string(), currency.clone()))
+    }
+
+    /// Account and whether it is held by the wallet
+    fn get_mut(&mut self, key: &AccountKey) -> Option<(&mut StoredBalance, bool)> {
+        match self.local.get_mut(key) {
+            Some(account) => Some((account, false)),
+            None => self.wallet.get_mut(key).map(|account| (account, true)),
+        }
+    }
+}
 
+/// Balances of players. Every operation is done under a single lock, together with writing
+/// the result to the store and the ledger, so concurrent operations can't interleave.
+///
+/// A player has a separate balance in every currency, operations use the currency
+/// the player joined with.
+///
+/// Balance changes of REAL mode players are delegated to the operator's wallet instead, which
+/// keeps their balance. Wallet requests are made without holding the lock, completed requests
+/// are recorded in the ledger with the balance reported by the wallet.
+///
+/// Bets, wins and refunds carry a transaction id, a replayed transaction returns the result
+/// of the first one. Wallet requests are retried with the same transaction id, so the wallet
+/// can recognize them as well.
 #[derive(Debug, Clone)]
 pub struct BalanceSystem {
-    balance_map: Arc<RwLock<HashMap<String, AtomicU64>>>,
-    reserved_money_map: Arc<RwLock<HashMap<String, AtomicU64>>>,
+    accounts: Arc<Mutex<Accounts>>,
+    store: Arc<dyn BalanceStore>,
+    ledger: Ledger,
+    wallet: Option<Arc<dyn WalletProvider>>,
 }
 
 impl BalanceSystem {
-    pub fn new() -> Self {
+    pub fn new(
+        store: Arc<dyn BalanceStore>,
+        ledger: Ledger,
+        wallet: Option<Arc<dyn WalletProvider>>,
+    ) -> Self {
+        // transactions completed before a restart are recognized when they are replayed
+        let mut completed = CompletedTransactions::default();
+        for entry in ledger
+            .recent_entries(MAX_COMPLETED_TRANSACTIONS)
+            .into_iter()
+            .rev()
+        {
+            match entry.kind {
+                LedgerEntryKind::BetCommitted => completed.insert(&entry.tx_id, entry.amount),
+                LedgerEntryKind::Win | LedgerEntryKind::Refund => {
+                    completed.insert(&entry.tx_id, entry.balance_after)
+                }
+                _ => {}
+            }
+        }
+
         Self {
-            balance_map: Arc::new(RwLock::new(HashMap::new())),
-            reserved_money_map: Arc::new(RwLock::new(HashMap::new())),
+            accounts: Arc::new(Mutex::new(Accounts {
+                completed,
+                ..Default::default()
+            })),
+            store,
+            ledger,
+            wallet,
         }
     }
 
-    /// Ensures that a user with the given UUID has an entry in the balance map.
-    /// If the user does not exist, their balance is initialized.
-    pub fn ensure_balance(&self, uuid: String) {
-        let mut map = self.balance_map.write().unwrap();
-        map.entry(uuid.clone()).or_insert_with(|| AtomicU64::new(DEFAULT_GUEST_BALANCE));
+    /// Authenticates a REAL mode player with the session token of the operator.
+    /// Returns the player id of the wallet.
+    pub fn authenticate_wallet(&self, token: &str) -> Result<String, BalanceError> {
+        let wallet = self
+            .wallet
+            .as_ref()
+            .ok_or(BalanceError::WalletUnavailable)?;
+        let session = wallet.authenticate(token)?;
+        Ok(session.player_id)
+    }
+
+    /// Ensures that a user with the given UUID has an entry in the balance map, in the given
+    /// currency, and makes it the currency the player plays with.
+    /// If the user does not exist, their balance is restored from the store or initialized.
+    /// Balance of REAL mode players is fetched from the wallet.
+    pub fn ensure_balance(
+        &self,
+        uuid: String,
+        play_mode: PlayMode,
+        currency: &str,
+    ) -> Result<(), BalanceError> {
+        let key = (uuid.clone(), currency.to_string());
+        if play_mode == PlayMode::REAL {
+            let wallet = self
+                .wallet
+                .as_ref()
+                .ok_or(BalanceError::WalletUnavailable)?;
+            let balance = wallet.balance(&uuid)?;
+
+            let mut accounts = self.accounts.lock().unwrap();
+            accounts.wallet.entry(key).or_default().balance = balance;
+            accounts.currencies.insert(uuid, currency.to_string());
+            return Ok(());
+        }
+
+        let mut accounts = self.accounts.lock().unwrap();
+        accounts
+            .currencies
+            .insert(uuid.clone(), currency.to_string());
+        if accounts.local.contains_key(&key) {
+            return Ok(());
+        }
 
-        let mut map = self.reserved_money_map.write().unwrap();
-        map.entry(uuid.clone()).or_insert_with(|| AtomicU64::new(0));
+        let stored_balance = match self.store.load(&uuid, currency) {
+            Some(mut stored_balance) => {
+                // reservation left from before a restart doesn't belong to any bet anymore
+                if stored_balance.reserved > 0 {
+                    info!(
+                        "Released {} reserved by {} before restart",
+                        stored_balance.reserved, uuid
+                    );
+                    self.ledger.record(
+                        None,
+                        &uuid,
+                        currency,
+                        None,
+                        LedgerEntryKind::BetCancelled,
+                        stored_balance.reserved,
+                        stored_balance.balance,
+                    );
+                    stored_balance.reserved = 0;
+                    self.store.save(&uuid, currency, stored_balance);
+                }
+                stored_balance
+            }
+            None => {
+                let stored_balance = StoredBalance {
+                    balance: DEFAULT_GUEST_BALANCE,
+                    reserved: 0,
+                };
+                self.store.save(&uuid, currency, stored_balance);
+                self.ledger.record(
+                    None,
+                    &uuid,
+                    currency,
+                    None,
+                    LedgerEntryKind::OpeningBalance,
+                    DEFAULT_GUEST_BALANCE,
+                    DEFAULT_GUEST_BALANCE,
+                );
+                stored_balance
+            }
+        };
+
+        accounts.local.insert(key, stored_balance);
+        Ok(())
     }
 
-    /// Fetches the balance for a given user UUID. Returns 0 if the user does not exist.
+    /// Currency the player currently plays with
+    pub fn currency_of(&self, uuid: &str) -> Option<String> {
+        self.accounts.lock().unwrap().currencies.get(uuid).cloned()
+    }
+
+    /// Fetches the available balance for a given user UUID. Returns 0 if the user does not exist.
     pub fn fetch_balance(&self, uuid: &str) -> u64 {
-        let balance_map = match self.balance_map.read() {
-            Ok(m) => m,
-            Err(_) => return 0,
+        let mut accounts = self.accounts.lock().unwrap();
+        let Ok(key) = accounts.key(uuid) else {
+            return 0;
         };
+        accounts.get_mut(&key).map_or(0, |(account, _)| {
+            account.balance.saturating_sub(account.reserved)
+        })
+    }
 
-        let reserved_map = match self.reserved_money_map.read() {
-            Ok(m) => m,
-            Err(_) => return 0,
-        };
+    /// Replays the ledger and compares the result with the stored balances.
+    /// Returns the UUIDs of players, whose balance doesn't match the ledger.
+    pub fn verify_ledger(&self) -> Vec<String> {
+        // no balance changes while the ledger is replayed
+        let _accounts = self.accounts.lock().unwrap();
 
-        let balance = balance_map.get(uuid).map_or(0, |b| b.load(Ordering::Relaxed));
-        let reserved = reserved_map.get(uuid).map_or(0, |r| r.load(Ordering::Relaxed));
+        let mut mismatches = Vec::new();
+        for ((uuid, currency), ledger_balance) in self.ledger.replay() {
+            let stored_balance = self.store.load(&uuid, &currency).unwrap_or_default();
+            if stored_balance != ledger_balance {
+                warn!(
+                    "balance of {:?} in {} doesn't match the ledger! stored: {:?}, ledger: {:?}",
+                    uuid, currency, stored_balance, ledger_balance
+                );
+                mismatches.push(uuid);
+            }
+        }
+        mismatches
+    }
+
+    /// Adds the winning amount of the round to the balance of the user with the provided UUID.
+    /// Returns the new available balance.
+    pub fn add(
+        &self,
+        uuid: &str,
+        amount_to_add: u64,
+        round_id: u32,
+        tx_id: &str,
+    ) -> Result<u64, BalanceError> {
+        if let Some(wallet) = self.wallet_of(uuid) {
+            if let Some(result) = self.completed_result(tx_id) {
+                return Ok(result);
+            }
+            let balance = call_wallet(|| wallet.credit(uuid, tx_id, round_id, amount_to_add))?;
+            let available = self.update_wallet_balance(uuid, balance);
+            self.complete_wallet_transaction(
+                uuid,
+                tx_id,
+                round_id,
+                LedgerEntryKind::Win,
+                amount_to_add,
+                balance,
+                available,
+            );
+            return Ok(available);
+        }
+
+        let mut accounts = self.accounts.lock().unwrap();
+        if let Some(result) = accounts.completed.get(tx_id) {
+            info!("Replayed transaction {}", tx_id);
+            return Ok(result);
+        }
+        let key = accounts.key(uuid)?;
+        let (_, currency) = &key;
+        let account = accounts
+            .local
+            .get_mut(&key)
+            .ok_or(BalanceError::UnknownPlayer)?;
+
+        let balance = account
+            .balance
+            .checked_add(amount_to_add)
+            .ok_or(BalanceError::Overflow)?;
+        account.balance = balance;
+        let available = account.balance - account.reserved;
+        info!(
+            "Added {} {} to balance of {}. New balance: {}",
+            amount_to_add, currency, uuid, available
+        );
 
-        balance.saturating_sub(reserved)
+        self.store.save(uuid, currency, *account);
+        self.ledger.record(
+            Some(tx_id),
+            uuid,
+            currency,
+            Some(round_id),
+            LedgerEntryKind::Win,
+            amount_to_add,
+            available,
+        );
+        accounts.completed.insert(tx_id, available);
+        Ok(available)
     }
 
-    /// Adds the given amount to the balance of the user with the provided UUID.
-    pub fn add(&self, uuid: &str, amount_to_add: u64) -> Result<u64, ()> {
-        let map = self.balance_map.read().unwrap();
-        if let Some(balance) = map.get(uuid) {
-            let new_balance = balance.fetch_add(amount_to_add, Ordering::SeqCst) + amount_to_add;
-            info!("Added {} to balance of {}. New balance: {}", amount_to_add, uuid, new_balance);
-            Ok(new_balance)
-        } else {
-            Err(())
-        }
-    }
-
-    /// Subtracts the given amount from the balance of the user with the provided UUID.
-    /// Returns an error if the user does not exist or if the subtraction would cause an underflow.
-    fn sub(&self, uuid: &str, amount_to_sub: u64) -> Result<u64, ()> {
-        let map = self.balance_map.read().unwrap();
-        if let Some(balance) = map.get(uuid) {
-            let current_balance = balance.load(Ordering::SeqCst);
-            if current_balance >= amount_to_sub {
-                let new_balance = balance.fetch_sub(amount_to_sub, Ordering::SeqCst) - amount_to_sub;
-                info!("Subtracted {} from balance of {}. New balance: {}", amount_to_sub, uuid, new_balance);
-                Ok(new_balance)
-            } else {
-                info!("Failed to subtract {} from balance of {}. Current balance: {}", amount_to_sub, uuid, current_balance);
-                Err(())
+    /// Reserves the bet amount of the round, replacing the previous reservation.
+    /// Reserving 0 cancels the bet. Returns the new available balance.
+    pub fn reserve_bet_amount(
+        &self,
+        uuid: &str,
+        amount_to_reserve: u64,
+        round_id: u32,
+    ) -> Result<u64, BalanceError> {
+        let mut accounts = self.accounts.lock().unwrap();
+        let key = accounts.key(uuid)?;
+        let (account, is_wallet) = accounts.get_mut(&key).ok_or(BalanceError::UnknownPlayer)?;
+
+        if account.balance < amount_to_reserve {
+            info!(
+                "Failed to reserve {} from balance of {}. Current balance: {}",
+                amount_to_reserve, uuid, account.balance
+            );
+            return Err(BalanceError::InsufficientFunds);
+        }
+
+        let previous_amount = account.reserved;
+        account.reserved = amount_to_reserve;
+        let available = account.balance - account.reserved;
+        // reservations of wallet players only live here, until the bet is committed
+        if is_wallet {
+            return Ok(available);
+        }
+
+        let (_, currency) = &key;
+        self.store.save(uuid, currency, *account);
+        if previous_amount > 0 {
+            self.ledger.record(
+                None,
+                uuid,
+                currency,
+                Some(round_id),
+                LedgerEntryKind::BetCancelled,
+                previous_amount,
+                account.balance,
+            );
+        }
+        if amount_to_reserve > 0 {
+            self.ledger.record(
+                None,
+                uuid,
+                currency,
+                Some(round_id),
+                LedgerEntryKind::BetReserved,
+                amount_to_reserve,
+                available,
+            );
+        }
+        Ok(available)
+    }
+
+    /// Cancels the reserved bet amount, so it is available again. Returns the released amount.
+    pub fn release_reserved_bet_amount(
+        &self,
+        uuid: &str,
+        round_id: u32,
+    ) -> Result<u64, BalanceError> {
+        let mut accounts = self.accounts.lock().unwrap();
+        let key = accounts.key(uuid)?;
+        let (account, is_wallet) = accounts.get_mut(&key).ok_or(BalanceError::UnknownPlayer)?;
+
+        let reserved_amount = account.reserved;
+        if reserved_amount > 0 {
+            account.reserved = 0;
+            if !is_wallet {
+                let (_, currency) = &key;
+                self.store.save(uuid, currency, *account);
+                self.ledger.record(
+                    None,
+                    uuid,
+                    currency,
+                    Some(round_id),
+                    LedgerEntryKind::BetCancelled,
+                    reserved_amount,
+                    account.balance,
+                );
             }
-        } else {
-            Err(())
+            info!("Released {} reserved by {}", reserved_amount, uuid);
         }
+        Ok(reserved_amount)
     }
 
-    /// Checks if the user has enough balance to subtract the given amount.
-    /// Returns true if the subtraction is possible, otherwise false.
-    fn can_sub(&self, uuid: &str, amount_to_sub: u64) -> bool {
-        let map = self.balance_map.read().unwrap();
-        if let Some(balance) = map.get(uuid) {
-            let current_balance = balance.load(Ordering::SeqCst);
-            current_balance >= amount_to_sub
-        } else {
-            false
+    /// Pays back a committed bet of a round, that couldn't be completed.
+    /// `bet_tx_id` is the transaction id the bet was committed with.
+    /// Returns the new available balance.
+    pub fn refund_bet_amount(
+        &self,
+        uuid: &str,
+        amount_to_refund: u64,
+        round_id: u32,
+        tx_id: &str,
+        bet_tx_id: &str,
+    ) -> Result<u64, BalanceError> {
+        if let Some(wallet) = self.wallet_of(uuid) {
+            if let Some(result) = self.completed_result(tx_id) {
+                return Ok(result);
+            }
+            let balance = call_wallet(|| wallet.rollback(uuid, tx_id, bet_tx_id, round_id))?;
+            let available = self.update_wallet_balance(uuid, balance);
+            self.complete_wallet_transaction(
+                uuid,
+                tx_id,
+                round_id,
+                LedgerEntryKind::Refund,
+                amount_to_refund,
+                balance,
+                available,
+            );
+            return Ok(available);
+        }
+
+        let mut accounts = self.accounts.lock().unwrap();
+        if let Some(result) = accounts.completed.get(tx_id) {
+            info!("Replayed transaction {}", tx_id);
+            return Ok(result);
         }
+        let key = accounts.key(uuid)?;
+        let (_, currency) = &key;
+        let account = accounts
+            .local
+            .get_mut(&key)
+            .ok_or(BalanceError::UnknownPlayer)?;
+
+        account.balance = account
+            .balance
+            .checked_add(amount_to_refund)
+            .ok_or(BalanceError::Overflow)?;
+        let available = account.balance - account.reserved;
+        info!(
+            "Refunded {} {} to {}. New balance: {}",
+            amount_to_refund, currency, uuid, available
+        );
+
+        self.store.save(uuid, currency, *account);
+        self.ledger.record(
+            Some(tx_id),
+            uuid,
+            currency,
+            Some(round_id),
+            LedgerEntryKind::Refund,
+            amount_to_refund,
+            available,
+        );
+        accounts.completed.insert(tx_id, available);
+        Ok(available)
     }
 
-    pub fn reserve_bet_amount(&self, uuid: &str, amount_to_reserve: u64) -> bool {
-        if self.can_sub(uuid, amount_to_reserve) {
-            if let Ok(map) = self.reserved_money_map.read() {
-                if let Some(amount) = map.get(uuid) {
-                    amount.store(amount_to_reserve, Ordering::SeqCst);
-                    return true;
-                }
+    /// Takes the reserved bet amount from the balance. Returns the committed amount.
+    /// A failed wallet debit is rolled back, as the wallet may have applied it anyway.
+    pub fn commit_reserved_bet_amount(
+        &self,
+        uuid: &str,
+        round_id: u32,
+        tx_id: &str,
+    ) -> Result<u64, BalanceError> {
+        if let Some(wallet) = self.wallet_of(uuid) {
+            if let Some(result) = self.completed_result(tx_id) {
+                return Ok(result);
+            }
+            let reserved_amount = {
+                let mut accounts = self.accounts.lock().unwrap();
+                let key = accounts.key(uuid)?;
+                let account = accounts
+                    .wallet
+                    .get_mut(&key)
+                    .ok_or(BalanceError::UnknownPlayer)?;
+                std::mem::take(&mut account.reserved)
+            };
+            if reserved_amount == 0 {
+                return Ok(0);
             }
+
+            let balance = match call_wallet(|| wallet.debit(uuid, tx_id, round_id, reserved_amount))
+            {
+                Ok(balance) => balance,
+                Err(err) => {
+                    // debit may be applied, even if its response was lost, so it's reverted
+                    let rollback_tx_id = transaction_id(round_id, uuid, TransactionKind::Refund);
+                    match call_wallet(|| wallet.rollback(uuid, &rollback_tx_id, tx_id, round_id)) {
+                        Ok(balance) => {
+                            self.update_wallet_balance(uuid, balance);
+                        }
+                        Err(rollback_err) => warn!(
+                            "unable to roll back the bet {:?} of {:?}! {:?}",
+                            tx_id, uuid, rollback_err
+                        ),
+                    }
+                    return Err(err.into());
+                }
+            };
+            self.update_wallet_balance(uuid, balance);
+            self.complete_wallet_transaction(
+                uuid,
+                tx_id,
+                round_id,
+                LedgerEntryKind::BetCommitted,
+                reserved_amount,
+                balance,
+                reserved_amount,
+            );
+            return Ok(reserved_amount);
+        }
+
+        let mut accounts = self.accounts.lock().unwrap();
+        if let Some(result) = accounts.completed.get(tx_id) {
+            info!("Replayed transaction {}", tx_id);
+            return Ok(result);
+        }
+        let key = accounts.key(uuid)?;
+        let (_, currency) = &key;
+        let account = accounts
+            .local
+            .get_mut(&key)
+            .ok_or(BalanceError::UnknownPlayer)?;
+
+        let reserved_amount = account.reserved;
+        // reservations never exceed the balance, checked in case the store was edited offline
+        let balance = account
+            .balance
+            .checked_sub(reserved_amount)
+            .ok_or(BalanceError::InsufficientFunds)?;
+        account.balance = balance;
+        account.reserved = 0;
+        info!(
+            "Subtracted {} {} from balance of {}. New balance: {}",
+            reserved_amount, currency, uuid, balance
+        );
+
+        if reserved_amount > 0 {
+            self.store.save(uuid, currency, *account);
+            self.ledger.record(
+                Some(tx_id),
+                uuid,
+                currency,
+                Some(round_id),
+                LedgerEntryKind::BetCommitted,
+                reserved_amount,
+                balance,
+            );
         }
-        false
+        accounts.completed.insert(tx_id, reserved_amount);
+        Ok(reserved_amount)
     }
 
-    pub fn commit_reserved_bet_amount(&self, uuid: &str) {
-        if let Ok(map) = self.reserved_money_map.read() {
-            if let Some(reserved_amount_atomic) = map.get(uuid) {
-                let reserved_amount = reserved_amount_atomic.load(Ordering::SeqCst);
-                let _ = self.sub(uuid, reserved_amount);
-                reserved_amount_atomic.store(0, Ordering::SeqCst);
+    /// Tops up the available balance of a FUN mode player to the refill amount, at most once
+    /// per cooldown. Balance above the refill amount is left as is.
+    /// Returns the new available balance.
+    pub fn refill(
+        &self,
+        uuid: &str,
+        refill_amount: u64,
+        cooldown: Duration,
+    ) -> Result<u64, BalanceError> {
+        let mut accounts = self.accounts.lock().unwrap();
+        let key = accounts.key(uuid)?;
+        if accounts.wallet.contains_key(&key) {
+            return Err(BalanceError::RefillNotAllowed);
+        }
+        if let Some(refilled_at) = accounts.refilled_at.get(&key) {
+            if refilled_at.elapsed() < cooldown {
+                return Err(BalanceError::RefillCooldown);
             }
         }
+        let account = accounts
+            .local
+            .get_mut(&key)
+            .ok_or(BalanceError::UnknownPlayer)?;
+
+        let available = account.balance - account.reserved;
+        if available >= refill_amount {
+            return Ok(available);
+        }
+        let amount_to_add = refill_amount - available;
+        account.balance += amount_to_add;
+        info!(
+            "Refilled {} {} to {}. New balance: {}",
+            amount_to_add, key.1, uuid, refill_amount
+        );
+
+        self.store.save(uuid, &key.1, *account);
+        self.ledger.record(
+            None,
+            uuid,
+            &key.1,
+            None,
+            LedgerEntryKind::Refill,
+            amount_to_add,
+            refill_amount,
+        );
+        accounts.refilled_at.insert(key, Instant::now());
+        Ok(refill_amount)
+    }
+
+    /// Result of the wallet transaction, if it was already completed
+    fn completed_result(&self, tx_id: &str) -> Option<u64> {
+        let result = self.accounts.lock().unwrap().completed.get(tx_id);
+        if result.is_some() {
+            info!("Replayed transaction {}", tx_id);
+        }
+        result
+    }
+
+    /// Records the wallet transaction in the ledger, with the balance reported by the wallet,
+    /// and remembers its result.
+    #[allow(clippy::too_many_arguments)]
+    fn complete_wallet_transaction(
+        &self,
+        uuid: &str,
+        tx_id: &str,
+        round_id: u32,
+        kind: LedgerEntryKind,
+        amount: u64,
+        balance: u64,
+        result: u64,
+    ) {
+        let mut accounts = self.accounts.lock().unwrap();
+        let currency = accounts.currencies.get(uuid).cloned().unwrap_or_default();
+        self.ledger
+            .record_wallet(tx_id, uuid, &currency, round_id, kind, amount, balance);
+        accounts.completed.insert(tx_id, result);
+    }
+
+    /// Wallet, that holds the balance of the player, if the player is in REAL mode
+    fn wallet_of(&self, uuid: &str) -> Option<Arc<dyn WalletProvider>> {
+        let accounts = self.accounts.lock().unwrap();
+        let key = accounts.key(uuid).ok()?;
+        if accounts.wallet.contains_key(&key) {
+            self.wallet.clone()
+        } else {
+            None
+        }
+    }
+
+    /// Caches the balance returned by the wallet, returns the available balance.
+    fn update_wallet_balance(&self, uuid: &str, balance: u64) -> u64 {
+        let mut accounts = self.accounts.lock().unwrap();
+        let Ok(key) = accounts.key(uuid) else {
+            return balance;
+        };
+        let account = accounts.wallet.entry(key).or_default();
+        account.balance = balance;
+        balance.saturating_sub(account.reserved)
+    }
+}
+
+/// Sends the request to the wallet, and repeats it when the wallet can't be reached.
+/// Repeating is safe, because the wallet recognizes the transaction id of the request.
+fn call_wallet<T>(request: impl Fn() -> Result<T, WalletError>) -> Result<T, WalletError> {
+    let mut result = request();
+    for _ in 0..WALLET_RETRIES {
+        if !matches!(result, Err(WalletError::Unavailable)) {
+            break;
+        }
+        warn!("wallet is not available, retrying");
+        result = request();
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::services::{
+        balance_store::InMemoryBalanceStore, currency::DEFAULT_CURRENCY,
+        mock_wallet_server::MockWalletServer, wallet_provider::HttpWalletProvider,
+    };
+
+    fn create_balance_system() -> BalanceSystem {
+        BalanceSystem::new(
+            Arc::new(InMemoryBalanceStore::new()),
+            Ledger::in_memory(),
+            None,
+        )
+    }
+
+    #[test]
+    fn test_ledger_replay_matches_balances() {
+        let balance_system = create_balance_system();
+        balance_system
+            .ensure_balance("a".to_string(), PlayMode::FUN, DEFAULT_CURRENCY)
+            .unwrap();
+        balance_system
+            .ensure_balance("b".to_string(), PlayMode::FUN, DEFAULT_CURRENCY)
+            .unwrap();
+
+        // round 1: a wins, b changes the bet and loses
+        balance_system.reserve_bet_amount("a", 100, 1).unwrap();
+        balance_system.reserve_bet_amount("b", 300, 1).unwrap();
+        balance_system.reserve_bet_amount("b", 200, 1).unwrap();
+        balance_system
+            .commit_reserved_bet_amount("a", 1, &transaction_id(1, "a", TransactionKind::Bet))
+            .unwrap();
+        balance_system
+            .commit_reserved_bet_amount("b", 1, &transaction_id(1, "b", TransactionKind::Bet))
+            .unwrap();
+        balance_system
+            .add("a", 250, 1, &transaction_id(1, "a", TransactionKind::Win))
+            .unwrap();
+
+        // round 2: a cancels the bet, b's bet is still reserved
+        balance_system.reserve_bet_amount("a", 50, 2).unwrap();
+        balance_system.reserve_bet_amount("a", 0, 2).unwrap();
+        balance_system.reserve_bet_amount("b", 70, 2).unwrap();
+
+        assert_eq!(
+            balance_system.fetch_balance("a"),
+            DEFAULT_GUEST_BALANCE + 150
+        );
+        assert_eq!(
+            balance_system.fetch_balance("b"),
+            DEFAULT_GUEST_BALANCE - 270
+        );
+        assert!(balance_system.verify_ledger().is_empty());
+    }
+
+    #[test]
+    fn test_release_and_refund_return_the_bet() {
+        let store = Arc::new(InMemoryBalanceStore::new());
+        let ledger = Ledger::in_memory();
+        let balance_system = BalanceSystem::new(store.clone(), ledger.clone(), None);
+        balance_system
+            .ensure_balance("a".to_string(), PlayMode::FUN, DEFAULT_CURRENCY)
+            .unwrap();
+
+        balance_system.reserve_bet_amount("a", 100, 1).unwrap();
+        assert_eq!(balance_system.release_reserved_bet_amount("a", 1), Ok(100));
+        assert_eq!(balance_system.release_reserved_bet_amount("a", 1), Ok(0));
+        assert_eq!(balance_system.fetch_balance("a"), DEFAULT_GUEST_BALANCE);
+
+        balance_system.reserve_bet_amount("a", 100, 2).unwrap();
+        let bet_tx_id = transaction_id(2, "a", TransactionKind::Bet);
+        let refund_tx_id = transaction_id(2, "a", TransactionKind::Refund);
+        balance_system
+            .commit_reserved_bet_amount("a", 2, &bet_tx_id)
+            .unwrap();
+        balance_system
+            .refund_bet_amount("a", 100, 2, &refund_tx_id, &bet_tx_id)
+            .unwrap();
+        assert_eq!(balance_system.fetch_balance("a"), DEFAULT_GUEST_BALANCE);
+
+        // reservation is released, when the balance is restored after a restart
+        balance_system.reserve_bet_amount("a", 100, 3).unwrap();
+        let restarted_balance_system = BalanceSystem::new(store, ledger, None);
+        restarted_balance_system
+            .ensure_balance("a".to_string(), PlayMode::FUN, DEFAULT_CURRENCY)
+            .unwrap();
+        assert_eq!(
+            restarted_balance_system.fetch_balance("a"),
+            DEFAULT_GUEST_BALANCE
+        );
+        assert!(restarted_balance_system.verify_ledger().is_empty());
+    }
+
+    #[test]
+    fn test_balances_are_kept_per_currency() {
+        let store = Arc::new(InMemoryBalanceStore::new());
+        let balance_system = BalanceSystem::new(store.clone(), Ledger::in_memory(), None);
+        balance_system
+            .ensure_balance("a".to_string(), PlayMode::FUN, "EUR")
+            .unwrap();
+        balance_system.reserve_bet_amount("a", 100, 1).unwrap();
+        balance_system
+            .commit_reserved_bet_amount("a", 1, &transaction_id(1, "a", TransactionKind::Bet))
+            .unwrap();
+        assert_eq!(balance_system.currency_of("a"), Some("EUR".to_string()));
+        assert_eq!(
+            balance_system.fetch_balance("a"),
+            DEFAULT_GUEST_BALANCE - 100
+        );
+
+        // player joins with another currency, which has its own balance
+        balance_system
+            .ensure_balance("a".to_string(), PlayMode::FUN, "USD")
+            .unwrap();
+        assert_eq!(balance_system.currency_of("a"), Some("USD".to_string()));
+        assert_eq!(balance_system.fetch_balance("a"), DEFAULT_GUEST_BALANCE);
+        balance_system
+            .add("a", 50, 2, &transaction_id(2, "a", TransactionKind::Win))
+            .unwrap();
+
+        assert_eq!(
+            store.load("a", "EUR").map(|b| b.balance),
+            Some(DEFAULT_GUEST_BALANCE - 100)
+        );
+        assert_eq!(
+            store.load("a", "USD").map(|b| b.balance),
+            Some(DEFAULT_GUEST_BALANCE + 50)
+        );
+        assert!(balance_system.verify_ledger().is_empty());
+    }
+
+    #[test]
+    fn test_refill_tops_up_fun_balance() {
+        let balance_system = create_balance_system();
+        balance_system
+            .ensure_balance("a".to_string(), PlayMode::FUN, DEFAULT_CURRENCY)
+            .unwrap();
+        let cooldown = Duration::from_secs(60);
+
+        // balance above the refill amount isn't changed
+        assert_eq!(
+            balance_system.refill("a", 1_000, cooldown),
+            Ok(DEFAULT_GUEST_BALANCE)
+        );
+
+        balance_system
+            .reserve_bet_amount("a", DEFAULT_GUEST_BALANCE - 400, 1)
+            .unwrap();
+        balance_system
+            .commit_reserved_bet_amount("a", 1, &transaction_id(1, "a", TransactionKind::Bet))
+            .unwrap();
+        assert_eq!(balance_system.refill("a", 1_000, cooldown), Ok(1_000));
+        assert_eq!(balance_system.fetch_balance("a"), 1_000);
+
+        balance_system.reserve_bet_amount("a", 1_000, 2).unwrap();
+        balance_system
+            .commit_reserved_bet_amount("a", 2, &transaction_id(2, "a", TransactionKind::Bet))
+            .unwrap();
+        assert_eq!(
+            balance_system.refill("a", 1_000, cooldown),
+            Err(BalanceError::RefillCooldown)
+        );
+        assert_eq!(balance_system.refill("a", 1_000, Duration::ZERO), Ok(1_000));
+        assert_eq!(
+            balance_system.refill("unknown", 1_000, cooldown),
+            Err(BalanceError::UnknownPlayer)
+        );
+        assert!(balance_system.verify_ledger().is_empty());
+    }
+
+    #[test]
+    fn test_typed_errors() {
+        let balance_system = create_balance_system();
+        balance_system
+            .ensure_balance("a".to_string(), PlayMode::FUN, DEFAULT_CURRENCY)
+            .unwrap();
+
+        assert_eq!(
+            balance_system.add("unknown", 1, 1, "1:unknown:win"),
+            Err(BalanceError::UnknownPlayer)
+        );
+        assert_eq!(
+            balance_system.reserve_bet_amount("a", DEFAULT_GUEST_BALANCE + 1, 1),
+            Err(BalanceError::InsufficientFunds)
+        );
+        assert_eq!(
+            balance_system.add("a", u64::MAX, 1, "1:a:win"),
+            Err(BalanceError::Overflow)
+        );
+        assert_eq!(balance_system.fetch_balance("a"), DEFAULT_GUEST_BALANCE);
+    }
+
+    #[test]
+    fn test_real_mode_players_use_the_wallet() {
+        let server = MockWalletServer::start();
+        server.add_player("token-1", "player-1", 1_000);
+        let ledger = Ledger::in_memory();
+        let balance_system = BalanceSystem::new(
+            Arc::new(InMemoryBalanceStore::new()),
+            ledger.clone(),
+            Some(Arc::new(HttpWalletProvider::new(
+                &server.url(),
+                "key",
+                2_000,
+            ))),
+        );
+
+        assert_eq!(
+            balance_system.authenticate_wallet("token-2"),
+            Err(BalanceError::UnknownPlayer)
+        );
+        let player_id = balance_system.authenticate_wallet("token-1").unwrap();
+        balance_system
+            .ensure_balance(player_id.clone(), PlayMode::REAL, "EUR")
+            .unwrap();
+
+        // reservation is local, the bet is debited when it is committed
+        assert_eq!(
+            balance_system.reserve_bet_amount(&player_id, 400, 1),
+            Ok(600)
+        );
+        assert_eq!(server.balance(&player_id), Some(1_000));
+        assert_eq!(
+            balance_system.commit_reserved_bet_amount(&player_id, 1, "1:player-1:bet"),
+            Ok(400)
+        );
+        assert_eq!(server.balance(&player_id), Some(600));
+
+        assert_eq!(
+            balance_system.add(&player_id, 800, 1, "1:player-1:win"),
+            Ok(1_400)
+        );
+        assert_eq!(balance_system.fetch_balance(&player_id), 1_400);
+
+        balance_system
+            .reserve_bet_amount(&player_id, 300, 2)
+            .unwrap();
+        balance_system
+            .commit_reserved_bet_amount(&player_id, 2, "2:player-1:bet")
+            .unwrap();
+        assert_eq!(
+            balance_system.refund_bet_amount(
+                &player_id,
+                300,
+                2,
+                "2:player-1:refund",
+                "2:player-1:bet"
+            ),
+            Ok(1_400)
+        );
+        assert_eq!(server.balance(&player_id), Some(1_400));
+
+        assert_eq!(
+            balance_system.refill(&player_id, 10_000, Duration::ZERO),
+            Err(BalanceError::RefillNotAllowed)
+        );
+
+        // wallet transactions are recorded with the balance reported by the wallet
+        let entries: Vec<(LedgerEntryKind, u64, u64)> = ledger
+            .entries_of(&player_id, 10)
+            .iter()
+            .rev()
+            .map(|entry| (entry.kind, entry.amount, entry.balance_after))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                (LedgerEntryKind::BetCommitted, 400, 600),
+                (LedgerEntryKind::Win, 800, 1_400),
+                (LedgerEntryKind::BetCommitted, 300, 1_100),
+                (LedgerEntryKind::Refund, 300, 1_400),
+            ]
+        );
+        assert!(ledger
+            .entries_of(&player_id, 10)
+            .iter()
+            .all(|entry| entry.currency == "EUR"));
+        assert!(balance_system.verify_ledger().is_empty());
+    }
+
+    #[test]
+    fn test_replayed_transactions_are_applied_once() {
+        let balance_system = create_balance_system();
+        balance_system
+            .ensure_balance("a".to_string(), PlayMode::FUN, DEFAULT_CURRENCY)
+            .unwrap();
+        let bet_tx_id = transaction_id(1, "a", TransactionKind::Bet);
+        let win_tx_id = transaction_id(1, "a", TransactionKind::Win);
+
+        balance_system.reserve_bet_amount("a", 100, 1).unwrap();
+        assert_eq!(
+            balance_system.commit_reserved_bet_amount("a", 1, &bet_tx_id),
+            Ok(100)
+        );
+        assert_eq!(
+            balance_system.add("a", 250, 1, &win_tx_id),
+            Ok(DEFAULT_GUEST_BALANCE + 150)
+        );
+
+        // retries return the original result, even if the balance changed in between
+        balance_system.reserve_bet_amount("a", 50, 2).unwrap();
+        assert_eq!(
+            balance_system.commit_reserved_bet_amount("a", 1, &bet_tx_id),
+            Ok(100)
+        );
+        assert_eq!(
+            balance_system.add("a", 250, 1, &win_tx_id),
+            Ok(DEFAULT_GUEST_BALANCE + 150)
+        );
+        assert_eq!(
+            balance_system.fetch_balance("a"),
+            DEFAULT_GUEST_BALANCE + 100
+        );
+        assert!(balance_system.verify_ledger().is_empty());
+    }
+
+    #[test]
+    fn test_replayed_transactions_are_applied_once_after_restart() {
+        let store = Arc::new(InMemoryBalanceStore::new());
+        let ledger = Ledger::in_memory();
+        let balance_system = BalanceSystem::new(store.clone(), ledger.clone(), None);
+        balance_system
+            .ensure_balance("a".to_string(), PlayMode::FUN, DEFAULT_CURRENCY)
+            .unwrap();
+        let bet_tx_id = transaction_id(1, "a", TransactionKind::Bet);
+        let win_tx_id = transaction_id(1, "a", TransactionKind::Win);
+
+        balance_system.reserve_bet_amount("a", 100, 1).unwrap();
+        balance_system
+            .commit_reserved_bet_amount("a", 1, &bet_tx_id)
+            .unwrap();
+        balance_system.add("a", 250, 1, &win_tx_id).unwrap();
+        drop(balance_system);
+
+        let restarted_balance_system = BalanceSystem::new(store, ledger, None);
+        restarted_balance_system
+            .ensure_balance("a".to_string(), PlayMode::FUN, DEFAULT_CURRENCY)
+            .unwrap();
+        assert_eq!(
+            restarted_balance_system.commit_reserved_bet_amount("a", 1, &bet_tx_id),
+            Ok(100)
+        );
+        assert_eq!(
+            restarted_balance_system.add("a", 250, 1, &win_tx_id),
+            Ok(DEFAULT_GUEST_BALANCE + 150)
+        );
+        assert_eq!(
+            restarted_balance_system.fetch_balance("a"),
+            DEFAULT_GUEST_BALANCE + 150
+        );
+        assert!(restarted_balance_system.verify_ledger().is_empty());
+    }
+
+    #[test]
+    fn test_wallet_retries_are_applied_once() {
+        let server = MockWalletServer::start();
+        server.add_player("token-1", "player-1", 1_000);
+        let balance_system = BalanceSystem::new(
+            Arc::new(InMemoryBalanceStore::new()),
+            Ledger::in_memory(),
+            Some(Arc::new(HttpWalletProvider::new(
+                &server.url(),
+                "key",
+                2_000,
+            ))),
+        );
+        let player_id = balance_system.authenticate_wallet("token-1").unwrap();
+        balance_system
+            .ensure_balance(player_id.clone(), PlayMode::REAL, "EUR")
+            .unwrap();
+
+        // wallet takes the bet, but the response is lost, so the debit is retried
+        balance_system
+            .reserve_bet_amount(&player_id, 400, 1)
+            .unwrap();
+        server.lose_next_responses(1);
+        assert_eq!(
+            balance_system.commit_reserved_bet_amount(&player_id, 1, "1:player-1:bet"),
+            Ok(400)
+        );
+        assert_eq!(server.balance(&player_id), Some(600));
+
+        server.lose_next_responses(2);
+        assert_eq!(
+            balance_system.add(&player_id, 800, 1, "1:player-1:win"),
+            Ok(1_400)
+        );
+        assert_eq!(
+            balance_system.add(&player_id, 800, 1, "1:player-1:win"),
+            Ok(1_400)
+        );
+        assert_eq!(server.balance(&player_id), Some(1_400));
+
+        // every retry is lost
+        balance_system
+            .reserve_bet_amount(&player_id, 100, 2)
+            .unwrap();
+        server.lose_next_responses(WALLET_RETRIES + 1);
+        assert_eq!(
+            balance_system.commit_reserved_bet_amount(&player_id, 2, "2:player-1:bet"),
+            Err(BalanceError::WalletUnavailable)
+        );
+        // debit was applied, but the player doesn't have a bet in the round, so it's rolled back
+        assert_eq!(server.balance(&player_id), Some(1_400));
+        assert_eq!(balance_system.fetch_balance(&player_id), 1_400);
+    }
+
+    #[test]
+    fn test_concurrent_bets_never_overspend() {
+        let balance_system = create_balance_system();
+        balance_system
+            .ensure_balance("a".to_string(), PlayMode::FUN, DEFAULT_CURRENCY)
+            .unwrap();
+
+        let bet_amount = 1_000;
+        let threads: Vec<_> = (0..16)
+            .map(|round_id| {
+                let balance_system = balance_system.clone();
+                thread::spawn(move || {
+                    let mut committed = 0;
+                    for i in 0..200 {
+                        if balance_system
+                            .reserve_bet_amount("a", bet_amount, round_id)
+                            .is_ok()
+                        {
+                            let tx_id = format!("{}:{}:a:bet", round_id, i);
+                            committed += balance_system
+                                .commit_reserved_bet_amount("a", round_id, &tx_id)
+                                .unwrap();
+                        }
+                    }
+                    committed
+                })
+            })
+            .collect();
+        let total_committed: u64 = threads.into_iter().map(|t| t.join().unwrap()).sum();
+
+        // threads overwrite each other's reservation, but a commit only takes the amount,
+        // that is currently reserved, so no money is created or lost
+        assert!(total_committed <= DEFAULT_GUEST_BALANCE);
+        assert_eq!(
+            balance_system.fetch_balance("a"),
+            DEFAULT_GUEST_BALANCE - total_committed
+        );
+        assert!(balance_system.verify_ledger().is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_credits_are_not_lost() {
+        let balance_system = create_balance_system();
+        let uuids: Vec<String> = (0..4).map(|i| format!("player-{}", i)).collect();
+        for uuid in &uuids {
+            balance_system
+                .ensure_balance(uuid.clone(), PlayMode::FUN, DEFAULT_CURRENCY)
+                .unwrap();
+        }
+
+        let threads: Vec<_> = (0..16)
+            .map(|i| {
+                let balance_system = balance_system.clone();
+                let uuid = uuids[i % uuids.len()].clone();
+                thread::spawn(move || {
+                    for j in 0..500 {
+                        let tx_id = format!("{}:{}:{}", i, j, uuid);
+                        balance_system
+                            .add(&uuid, 3, 1, &format!("{}:win", tx_id))
+                            .unwrap();
+                        balance_system.reserve_bet_amount(&uuid, 2, 1).unwrap();
+                        balance_system
+                            .commit_reserved_bet_amount(&uuid, 1, &format!("{}:bet", tx_id))
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        // a commit only takes the amount, that is currently reserved,
+        // so every iteration nets between +1 and +3
+        for uuid in &uuids {
+            let balance = balance_system.fetch_balance(uuid);
+            assert!(balance >= DEFAULT_GUEST_BALANCE + 4 * 500);
+            assert!(balance <= DEFAULT_GUEST_BALANCE + 4 * 500 * 3);
+        }
+        assert!(balance_system.verify_ledger().is_empty());
     }
 }
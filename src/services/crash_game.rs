@@ -1,90 +1,102 @@
 use std::{
+    io,
     sync::{
-        atomic::{AtomicBool, AtomicU32, Ordering},
+        atomic::{AtomicU32, Ordering},
         Arc, Mutex,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use actix::{spawn, Addr};
 use actix_web::rt::time;
 use log::{info, warn};
-use map_range::MapRange;
 
-use crate::services::message_types::{BettingTimerUpdate, GameRoundUpdate};
+use crate::services::message_types::{BettingTimerFinished, BettingTimerUpdate, GameRoundUpdate};
 
 use super::{
     crash_game_math::{sha256, CrashGameMath},
     game_server::GameServer,
-    message_types::{BettingTimerStarted, GameError, GameFinished, GameStarted},
+    message_types::{BettingTimerStarted, GameError, GameFinished, GameStarted, RoundResult},
+    seed_chain::SeedChain,
 };
 
-#[derive(Debug, Clone, Copy)]
-pub enum GameState {
-    Idle,
-    BettingInProgress,
-    GameInProgress,
-}
-
-impl Into<u8> for GameState {
-    fn into(self) -> u8 {
-        match self {
-            GameState::Idle => 0,
-            GameState::BettingInProgress => 1,
-            GameState::GameInProgress => 2,
-        }
-    }
-}
-
 #[derive(Debug, Clone)]
 pub struct GameData {
-    pub game_state: GameState,
     pub multiplier: u32,
     /// in milliseconds
     pub betting_time_left_ms: u32,
     /// in milliseconds
     pub round_time_elapsed_ms: u32,
+    pub multiplier_growth_rate: f64,
 }
 
 #[derive(Debug, Clone)]
 pub struct CrashGame {
-    is_betting_in_progress: Arc<AtomicBool>,
-    is_game_round_in_progress: Arc<AtomicBool>,
-    /// in seconds
-    betting_time_left: Arc<AtomicU32>,
-    /// in seconds
-    round_time_elapsed: Arc<AtomicU32>,
+    /// room the game loop belongs to
+    room_id: String,
+    /// in milliseconds
+    betting_time_left_ms: Arc<AtomicU32>,
+    /// in milliseconds
+    round_time_elapsed_ms: Arc<AtomicU32>,
     current_multiplier: Arc<AtomicU32>,
+    /// crash point of the running round
+    crash_point: Arc<AtomicU32>,
+    round_started_at: Arc<Mutex<Option<Instant>>>,
+    /// changes, when the betting timer is restarted or stopped, older timers stop ticking
+    betting_timer_id: Arc<AtomicU32>,
     game_server_addr: Option<Addr<GameServer>>,
+    /// in seconds
     max_betting_time_duration: u32,
+    /// in milliseconds
+    tick_interval_ms: u64,
     server_seed: String,
-    next_round_server_seed: String,
+    seed_chain: SeedChain,
     client_seed: Arc<Mutex<String>>,
+    /// seeds of the players, who placed bets in the current round
+    client_seed_inputs: Arc<Mutex<Vec<String>>>,
+    /// public salt mixed into the client seed
+    client_seed_salt: String,
     house_edge_pct: f32,
+    multiplier_growth_rate: f64,
     round_id: u32,
 }
 
-struct RoundResult {
-    multiplier: u32,
-    /// in seconds
-    animation_duration: u32,
+struct RoundOutcome {
+    /// final multiplier, where the round crashes
+    crash_point: u32,
+    /// time the multiplier curve needs to reach crash_point, in milliseconds
+    duration_ms: u64,
 }
 
 impl CrashGame {
-    pub fn new(betting_time_duration: u32, house_edge_pct: f32) -> Self {
+    pub fn new(
+        room_id: String,
+        betting_time_duration: u32,
+        house_edge_pct: f32,
+        multiplier_growth_rate: f64,
+        tick_interval_ms: u64,
+        seed_chain: SeedChain,
+        client_seed_salt: String,
+    ) -> Self {
         Self {
-            is_betting_in_progress: Arc::new(AtomicBool::new(false)),
-            is_game_round_in_progress: Arc::new(AtomicBool::new(false)),
-            round_id: 32314,
-            betting_time_left: Arc::new(AtomicU32::new(0)),
-            round_time_elapsed: Arc::new(AtomicU32::new(0)),
+            room_id,
+            round_id: 0,
+            betting_time_left_ms: Arc::new(AtomicU32::new(0)),
+            round_time_elapsed_ms: Arc::new(AtomicU32::new(0)),
             current_multiplier: Arc::new(AtomicU32::new(0)),
+            crash_point: Arc::new(AtomicU32::new(0)),
+            round_started_at: Default::default(),
+            betting_timer_id: Arc::new(AtomicU32::new(0)),
             game_server_addr: None,
             max_betting_time_duration: betting_time_duration,
+            tick_interval_ms,
             server_seed: Default::default(),
-            next_round_server_seed: CrashGameMath::generate_seed(),
+            seed_chain,
             client_seed: Default::default(),
+            client_seed_inputs: Default::default(),
+            client_seed_salt,
             house_edge_pct: house_edge_pct,
+            multiplier_growth_rate,
         }
     }
 
@@ -92,164 +104,227 @@ impl CrashGame {
         self.game_server_addr = Option::from(addr);
     }
 
-    pub fn start_betting_timer(&mut self) {
-        let valid = match self.get_game_state() {
-            GameState::Idle => true,
-            _ => false,
-        };
-
-        if !valid {
-            // game is not idle, can't start new round
-            info!("betting timer is already running!");
-            return;
-        }
+    /// Starts the betting phase of a new round, the game server owns the round phase
+    /// and calls it only when the room is idle.
+    /// Fails, if the seed of the round can't be taken from the seed chain.
+    pub fn start_betting_timer(&mut self) -> io::Result<()> {
+        // server seeds are consumed backwards from the pre-committed chain
+        let (round_id, server_seed) = self.seed_chain.next_round()?;
 
         self.reset_game_data();
-        self.set_betting_in_progress(true);
+        self.round_id = round_id;
+        self.server_seed = server_seed;
 
-        self.round_id += 1;
-        self.server_seed = self.next_round_server_seed.clone();
-        self.next_round_server_seed = CrashGameMath::generate_seed();
-
-        let game = Arc::new(self.clone());
-
-        let mut interval = time::interval(Duration::from_secs(1));
-        let mut time_left = self.max_betting_time_duration;
-
-        game.game_server_addr
+        self.game_server_addr
             .as_ref()
             .unwrap()
             .do_send(BettingTimerStarted {
-                betting_time_left_ms: time_left * 1000,
-                round_id: game.round_id,
-                server_seed_hash: sha256(&game.server_seed),
-                next_round_server_seed_hash: sha256(&game.next_round_server_seed),
+                room_id: self.room_id.clone(),
+                betting_time_left_ms: self.max_betting_time_duration * 1000,
+                round_id: self.round_id,
+                server_seed_hash: sha256(&self.server_seed),
+                seed_chain_hash: self.seed_chain.terminal_hash(),
             });
 
-        spawn(async move {
-            loop {
-                if time_left <= 0 {
-                    info!("betting timer is over, no more bets!");
-                    game.on_betting_timer_finished();
-
-                    // todo: only start game, if at-least 3 players has placed bets
+        self.restart_betting_timer();
+        Ok(())
+    }
 
-                    // todo: get this from actual client
-                    let mut client_seed = game.client_seed.lock().unwrap();
-                    *client_seed = CrashGameMath::generate_seed();
-                    drop(client_seed);
+    /// Counts down the betting time of the current round from the start,
+    /// bets placed so far stay in the round
+    pub fn restart_betting_timer(&self) {
+        let game = Arc::new(self.clone());
+        let betting_timer_id = self.betting_timer_id.fetch_add(1, Ordering::SeqCst) + 1;
 
-                    game.start_game();
+        let mut interval = time::interval(Duration::from_millis(self.tick_interval_ms));
+        let betting_started_at = Instant::now();
+        let betting_time_duration_ms = self.max_betting_time_duration * 1000;
 
+        spawn(async move {
+            loop {
+                interval.tick().await;
+                if game.betting_timer_id.load(Ordering::SeqCst) != betting_timer_id {
                     return;
                 }
-                interval.tick().await;
 
-                time_left = game.betting_time_left.fetch_sub(1, Ordering::SeqCst);
+                let elapsed_ms = betting_started_at.elapsed().as_millis() as u32;
+                let time_left_ms = betting_time_duration_ms.saturating_sub(elapsed_ms);
+                game.betting_time_left_ms
+                    .store(time_left_ms, Ordering::SeqCst);
 
                 game.game_server_addr
                     .as_ref()
                     .unwrap()
                     .do_send(BettingTimerUpdate {
-                        betting_time_left_ms: time_left * 1000,
+                        room_id: game.room_id.clone(),
+                        betting_time_left_ms: time_left_ms,
                     });
+
+                if time_left_ms == 0 {
+                    info!("betting timer is over, no more bets!");
+                    game.on_betting_timer_finished();
+
+                    // game server closes the bets and starts the round, or waits for more players
+                    game.game_server_addr
+                        .as_ref()
+                        .unwrap()
+                        .do_send(BettingTimerFinished {
+                            room_id: game.room_id.clone(),
+                        });
+
+                    return;
+                }
             }
         });
     }
 
-    fn start_game(&self) {
-        let valid = match self.get_game_state() {
-            GameState::GameInProgress => true,
-            _ => false,
-        };
+    /// Stops the betting timer without finishing the betting phase, the round isn't started
+    pub fn stop_betting_timer(&self) {
+        self.betting_timer_id.fetch_add(1, Ordering::SeqCst);
+        self.betting_time_left_ms.store(0, Ordering::SeqCst);
+    }
 
-        if !valid {
-            info!("game is in-progress already!");
-            return;
-        }
+    /// Starts the round, after bets are closed
+    pub fn start_game(&self) {
+        // bets are closed, so the seeds of players can't change anymore
+        let client_seed_inputs = self.client_seed_inputs.lock().unwrap().clone();
+        *self.client_seed.lock().unwrap() =
+            CrashGameMath::derive_client_seed(&client_seed_inputs, &self.client_seed_salt);
 
-        if let Some(round_result) = self.get_round_result() {
-            let mut current_second: u32 = 0;
+        if let Some(round_outcome) = self.get_round_outcome() {
             let game = Arc::new(self.clone()); // or self.clone()
 
+            let round_started_at = Instant::now();
+            self.crash_point
+                .store(round_outcome.crash_point, Ordering::SeqCst);
+            *self.round_started_at.lock().unwrap() = Some(round_started_at);
+
             game.game_server_addr
                 .as_ref()
                 .unwrap()
-                .do_send(GameStarted {});
+                .do_send(GameStarted {
+                    room_id: game.room_id.clone(),
+                });
 
-            let mut interval = time::interval(Duration::from_secs(1));
+            let crash_at = round_started_at + Duration::from_millis(round_outcome.duration_ms);
+
+            let mut interval = time::interval(Duration::from_millis(self.tick_interval_ms));
             spawn(async move {
                 loop {
-                    if current_second >= round_result.animation_duration {
+                    let time_to_crash = crash_at.saturating_duration_since(Instant::now());
+                    let crashed = time::timeout(time_to_crash, interval.tick()).await.is_err();
+
+                    let elapsed_ms = round_started_at.elapsed().as_millis() as u64;
+                    if crashed || elapsed_ms >= round_outcome.duration_ms {
+                        game.current_multiplier
+                            .store(round_outcome.crash_point, Ordering::SeqCst);
+
                         // send updates to peers
                         game.game_server_addr
                             .as_ref()
                             .unwrap()
-                            .do_send(GameFinished {});
+                            .do_send(GameRoundUpdate {
+                                room_id: game.room_id.clone(),
+                                multiplier: round_outcome.crash_point,
+                                crashed: true,
+                            });
+                        // reveal the seeds, so players can verify the crash point
+                        game.game_server_addr
+                            .as_ref()
+                            .unwrap()
+                            .do_send(RoundResult {
+                                room_id: game.room_id.clone(),
+                                round_id: game.round_id,
+                                crash_point: round_outcome.crash_point,
+                                server_seed: game.server_seed.clone(),
+                                client_seed: game.client_seed.lock().unwrap().clone(),
+                                client_seed_inputs: game
+                                    .client_seed_inputs
+                                    .lock()
+                                    .unwrap()
+                                    .clone(),
+                                client_seed_salt: game.client_seed_salt.clone(),
+                                house_edge_pct: game.house_edge_pct,
+                            });
+                        game.game_server_addr
+                            .as_ref()
+                            .unwrap()
+                            .do_send(GameFinished {
+                                room_id: game.room_id.clone(),
+                            });
                         game.on_game_finished();
                         return;
                     }
-                    interval.tick().await;
-                    current_second += 1;
 
-                    let current_multiplier = current_second.map_range(
-                        0..round_result.animation_duration,
-                        100..round_result.multiplier,
-                    );
+                    // multiplier is derived from elapsed time, it never goes past the crash point
+                    let current_multiplier =
+                        CrashGameMath::multiplier_at(elapsed_ms, game.multiplier_growth_rate)
+                            .min(round_outcome.crash_point);
 
                     // info!("current_multiplier {:?}", current_multiplier);
                     game.current_multiplier
                         .store(current_multiplier, Ordering::SeqCst);
-                    game.round_time_elapsed
-                        .store(current_second, Ordering::SeqCst);
+                    game.round_time_elapsed_ms
+                        .store(elapsed_ms as u32, Ordering::SeqCst);
 
                     // send updates to peers
                     game.game_server_addr
                         .as_ref()
                         .unwrap()
                         .do_send(GameRoundUpdate {
+                            room_id: game.room_id.clone(),
                             multiplier: current_multiplier,
+                            crashed: false,
                         });
                 }
             });
         } else {
             warn!("error in round result!");
             // error
+            self.game_server_addr.as_ref().unwrap().do_send(GameError {
+                room_id: self.room_id.clone(),
+            });
             self.game_server_addr
                 .as_ref()
                 .unwrap()
-                .do_send(GameError {});
-            self.game_server_addr
-                .as_ref()
-                .unwrap()
-                .do_send(GameFinished {});
+                .do_send(GameFinished {
+                    room_id: self.room_id.clone(),
+                });
             self.on_game_finished();
         }
     }
 
+    /// Sets the seeds of the players, that the client seed of the current round is derived from.
+    pub fn set_client_seed_inputs(&self, client_seed_inputs: Vec<String>) {
+        *self.client_seed_inputs.lock().unwrap() = client_seed_inputs;
+    }
+
     pub fn get_game_data(&self) -> GameData {
-        let game_state = self.get_game_state();
         GameData {
-            game_state,
             multiplier: self.current_multiplier.load(Ordering::Relaxed),
-            betting_time_left_ms: self.betting_time_left.load(Ordering::Relaxed) * 1000,
-            round_time_elapsed_ms: self.round_time_elapsed.load(Ordering::Relaxed) * 1000,
+            betting_time_left_ms: self.betting_time_left_ms.load(Ordering::Relaxed),
+            round_time_elapsed_ms: self.round_time_elapsed_ms.load(Ordering::Relaxed),
+            multiplier_growth_rate: self.multiplier_growth_rate,
         }
     }
 
-    fn get_game_state(&self) -> GameState {
-        if self.is_betting_in_progress.load(Ordering::SeqCst) {
-            return GameState::BettingInProgress;
-        }
-
-        if self.is_game_round_in_progress.load(Ordering::SeqCst) {
-            return GameState::GameInProgress;
+    /// Multiplier of the running round at the given moment.
+    /// Returns None if no round is running at that moment, or the round has crashed by then.
+    pub fn get_multiplier_at(&self, at: Instant) -> Option<u32> {
+        let round_started_at = (*self.round_started_at.lock().unwrap())?;
+        let elapsed_ms = at.checked_duration_since(round_started_at)?.as_millis() as u64;
+
+        let crash_point = self.crash_point.load(Ordering::SeqCst);
+        let round_duration_ms =
+            CrashGameMath::round_duration_ms(crash_point, self.multiplier_growth_rate);
+        if elapsed_ms >= round_duration_ms {
+            return None;
         }
 
-        GameState::Idle
+        Some(CrashGameMath::multiplier_at(elapsed_ms, self.multiplier_growth_rate).min(crash_point))
     }
 
-    fn get_round_result(&self) -> Option<RoundResult> {
+    fn get_round_outcome(&self) -> Option<RoundOutcome> {
         let client_seed = self.client_seed.lock().unwrap();
 
         if let Some(crash_point) = CrashGameMath::generate_crash_point(
@@ -258,10 +333,13 @@ impl CrashGame {
             &self.house_edge_pct,
             &self.round_id,
         ) {
-            let multiplier_u32 = (crash_point * 100.0) as u32;
-            return Some(RoundResult {
-                multiplier: multiplier_u32,
-                animation_duration: 10,
+            let crash_point = (crash_point * 100.0).round() as u32;
+            return Some(RoundOutcome {
+                crash_point,
+                duration_ms: CrashGameMath::round_duration_ms(
+                    crash_point,
+                    self.multiplier_growth_rate,
+                ),
             });
         }
 
@@ -271,33 +349,22 @@ impl CrashGame {
     fn on_betting_timer_finished(&self) {
         info!("Betting timer finished!");
         // nb! this is needed
-        self.betting_time_left.store(0, Ordering::SeqCst);
-        self.set_betting_in_progress(false);
-        self.set_game_in_progress(true);
+        self.betting_time_left_ms.store(0, Ordering::SeqCst);
     }
 
     fn on_game_finished(&self) {
         info!("Game finished!");
 
-        // reset game states
-        self.set_game_in_progress(false);
-        self.set_betting_in_progress(false);
-
         self.reset_game_data();
     }
 
-    fn set_game_in_progress(&self, val: bool) {
-        self.is_game_round_in_progress.store(val, Ordering::SeqCst);
-    }
-
-    fn set_betting_in_progress(&self, val: bool) {
-        self.is_betting_in_progress.store(val, Ordering::SeqCst);
-    }
-
     fn reset_game_data(&self) {
-        self.betting_time_left
-            .store(self.max_betting_time_duration, Ordering::SeqCst);
-        self.round_time_elapsed.store(0, Ordering::SeqCst);
+        self.betting_time_left_ms
+            .store(self.max_betting_time_duration * 1000, Ordering::SeqCst);
+        self.round_time_elapsed_ms.store(0, Ordering::SeqCst);
         self.current_multiplier.store(0, Ordering::SeqCst);
+        self.crash_point.store(0, Ordering::SeqCst);
+        *self.round_started_at.lock().unwrap() = None;
+        self.client_seed_inputs.lock().unwrap().clear();
     }
 }
@@ -43,6 +43,30 @@ impl CrashGameMath {
             })
     }
 
+    /// Multiplier (x100, e.g. 250 = 2.50x) on the growth curve `e^(growth_rate * elapsed_ms)`.
+    /// Clients and verifiers can use the same formula to reproduce the round animation.
+    pub fn multiplier_at(elapsed_ms: u64, growth_rate: f64) -> u32 {
+        let multiplier = (growth_rate * elapsed_ms as f64).exp();
+        (multiplier * 100.0).floor() as u32
+    }
+
+    /// Time (in milliseconds) the growth curve needs to reach the given crash point (x100).
+    pub fn round_duration_ms(crash_point: u32, growth_rate: f64) -> u64 {
+        if crash_point <= 100 {
+            return 0;
+        }
+        let crash_point = crash_point as f64 / 100.0;
+        (crash_point.ln() / growth_rate).ceil() as u64
+    }
+
+    /// Client seed of a round, derived from the seeds of players who placed bets and a public salt.
+    /// Player seeds are hashed and sorted, so the result doesn't depend on the order of bets.
+    pub fn derive_client_seed(player_seeds: &[String], public_salt: &str) -> String {
+        let mut seed_hashes: Vec<String> = player_seeds.iter().map(|seed| sha256(seed)).collect();
+        seed_hashes.sort();
+        sha256(&format!("{}:{}", seed_hashes.join(":"), public_salt))
+    }
+
     pub fn generate_seed() -> String {
         // Generate a random seed
         let mut rng = rand::thread_rng();
@@ -53,7 +77,8 @@ impl CrashGameMath {
         seed
     }
 
-    fn generate_round_hex_hash(server_seed: &str, client_seed: &str, round_id: &u32) -> String {
+    /// HMAC-SHA256 of the round, that the crash point is derived from
+    pub fn generate_round_hex_hash(server_seed: &str, client_seed: &str, round_id: &u32) -> String {
         let mut mac = HmacSha256::new_from_slice(server_seed.as_bytes())
             .expect("HMAC can take key of any size");
         let salt = ";jIm?8WmS;KX@VZxu9yd4HdS5M";
@@ -133,4 +158,39 @@ mod tests {
         println!("max_val: {:?}", max_val);
         // println!("Observed house edge: {:.2}%", (1.0 - 1.0 / average_result) * 100.0);
     }
+
+    #[test]
+    fn test_derive_client_seed_ignores_bet_order() {
+        let player_seeds = vec!["lucky".to_string(), "seven".to_string(), "".to_string()];
+        let mut reversed_seeds = player_seeds.clone();
+        reversed_seeds.reverse();
+
+        assert_eq!(
+            CrashGameMath::derive_client_seed(&player_seeds, "salt"),
+            CrashGameMath::derive_client_seed(&reversed_seeds, "salt")
+        );
+        assert_ne!(
+            CrashGameMath::derive_client_seed(&player_seeds, "salt"),
+            CrashGameMath::derive_client_seed(&player_seeds[..1], "salt")
+        );
+        assert_ne!(
+            CrashGameMath::derive_client_seed(&player_seeds, "salt"),
+            CrashGameMath::derive_client_seed(&player_seeds, "pepper")
+        );
+    }
+
+    #[test]
+    fn test_multiplier_curve_reaches_crash_point_at_round_duration() {
+        let growth_rate = 0.00006;
+
+        assert_eq!(CrashGameMath::multiplier_at(0, growth_rate), 100);
+        assert_eq!(CrashGameMath::round_duration_ms(100, growth_rate), 0);
+
+        for crash_point in [101, 150, 200, 341, 1000, 100_000] {
+            let duration_ms = CrashGameMath::round_duration_ms(crash_point, growth_rate);
+
+            assert!(CrashGameMath::multiplier_at(duration_ms, growth_rate) >= crash_point);
+            assert!(CrashGameMath::multiplier_at(duration_ms - 1, growth_rate) < crash_point);
+        }
+    }
 }
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+
+/// Currency of balances, that were stored before balances had a currency
+pub const DEFAULT_CURRENCY: &str = "FUN";
+
+pub fn default_currency() -> String {
+    DEFAULT_CURRENCY.to_string()
+}
+
+/// Bet limits of a currency, in cents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BetLimits {
+    pub min_bet: u64,
+    pub max_bet: u64,
+    /// maximum win amount of a single bet, bets are crashed out when they reach it
+    pub max_win: u64,
+}
+
+impl BetLimits {
+    pub fn new(min_bet: u64, max_bet: u64, max_win: u64) -> Result<Self, String> {
+        if min_bet == 0 || min_bet > max_bet || max_win < max_bet {
+            return Err(format!(
+                "invalid bet limits, min bet: {}, max bet: {}, max win: {}",
+                min_bet, max_bet, max_win
+            ));
+        }
+        Ok(Self {
+            min_bet,
+            max_bet,
+            max_win,
+        })
+    }
+}
+
+/// Parses bet limits of currencies, e.g. `EUR:10:100000:5000000,USD:10:100000:5000000`
+pub fn parse_currency_bet_limits(value: &str) -> Result<HashMap<String, BetLimits>, String> {
+    let mut currency_bet_limits = HashMap::new();
+    for item in value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+    {
+        let parts: Vec<&str> = item.split(':').map(str::trim).collect();
+        let [currency, min_bet, max_bet, max_win] = parts[..] else {
+            return Err(format!(
+                "{:?} must be currency:min_bet:max_bet:max_win",
+                item
+            ));
+        };
+        let min_bet = min_bet
+            .parse::<u64>()
+            .map_err(|_| format!("invalid min bet in {:?}", item))?;
+        let max_bet = max_bet
+            .parse::<u64>()
+            .map_err(|_| format!("invalid max bet in {:?}", item))?;
+        let max_win = max_win
+            .parse::<u64>()
+            .map_err(|_| format!("invalid max win in {:?}", item))?;
+        if currency.is_empty() {
+            return Err(format!("missing currency in {:?}", item));
+        }
+        currency_bet_limits.insert(
+            currency.to_string(),
+            BetLimits::new(min_bet, max_bet, max_win)?,
+        );
+    }
+    Ok(currency_bet_limits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_currency_bet_limits() {
+        let currency_bet_limits =
+            parse_currency_bet_limits("EUR:10:100000:5000000, USD:20:50000:50000").unwrap();
+        assert_eq!(
+            currency_bet_limits.get("EUR"),
+            Some(&BetLimits {
+                min_bet: 10,
+                max_bet: 100_000,
+                max_win: 5_000_000
+            })
+        );
+        assert_eq!(currency_bet_limits.get("USD").map(|l| l.min_bet), Some(20));
+        assert!(parse_currency_bet_limits("").unwrap().is_empty());
+
+        assert!(parse_currency_bet_limits("EUR:10:100").is_err());
+        assert!(parse_currency_bet_limits("EUR:ten:100:1000").is_err());
+        assert!(parse_currency_bet_limits("EUR:100:10:1000").is_err());
+        assert!(parse_currency_bet_limits("EUR:0:10:1000").is_err());
+        // max win below the max bet
+        assert!(parse_currency_bet_limits("EUR:10:1000:100").is_err());
+    }
+}
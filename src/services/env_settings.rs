@@ -1,4 +1,12 @@
-use std::env;
+use std::{collections::HashMap, env};
+
+use crate::routes::auth::PlayMode;
+
+use super::{
+    balance_store::BalanceStoreType,
+    currency::{parse_currency_bet_limits, BetLimits},
+    room::{parse_rooms, RoomSettings},
+};
 
 #[derive(Debug, Clone)]
 pub struct EnvSettings {
@@ -7,11 +15,60 @@ pub struct EnvSettings {
     pub server_port: u16,
     pub betting_time_duration: u32,
     pub house_edge_pct: f32,
+    /// growth rate `k` of the multiplier curve `e^(k * elapsed_ms)`
+    pub multiplier_growth_rate: f64,
+    /// game loop tick interval in milliseconds
+    pub game_tick_interval_ms: u64,
+    /// number of server seeds in a pre-committed hash chain
+    pub seed_chain_length: u32,
+    /// file where the hash chain position is persisted
+    pub seed_chain_state_file: String,
+    /// public salt mixed into the client seed, e.g. hash of a future block
+    pub client_seed_salt: String,
+    /// number of finished rounds kept in the round history
+    pub round_history_size: usize,
+    /// file the round history is persisted to, empty if the history is kept in memory only
+    pub round_history_file: String,
+    /// where player balances are stored, `memory` or `file`
+    pub balance_store_type: BalanceStoreType,
+    /// balance log file, used by the `file` balance store
+    pub balance_store_file: String,
+    /// transaction ledger file, used by the `file` balance store
+    pub ledger_file: String,
+    /// base url of the operator's wallet for REAL mode, empty if REAL mode is not available
+    pub wallet_url: String,
+    pub wallet_api_key: String,
+    /// timeout of wallet requests in milliseconds
+    pub wallet_timeout_ms: u64,
+    /// currency of FUN mode balances
+    pub fun_currency: String,
+    /// currency of REAL mode balances
+    pub real_currency: String,
+    /// bet limits in cents, of currencies without their own limits
+    pub bet_limits: BetLimits,
+    /// bet limits of currencies in cents
+    pub currency_bet_limits: HashMap<String, BetLimits>,
+    /// all bets are crashed out, when their potential payout reaches it, 0 = no cap
+    pub max_round_liability: u64,
+    /// in cents, FUN mode balance is topped up to this amount on refill
+    pub fun_refill_amount: u64,
+    /// minimum time between two refills of a player
+    pub fun_refill_cooldown_secs: u64,
+    /// pause between the crash of a round and the betting phase of the next round
+    pub round_cooldown_ms: u64,
+    /// players in the room needed to launch a round, 0 = no minimum
+    pub min_round_players: u32,
+    /// bets needed to launch a round, 0 = no minimum
+    pub min_round_bets: u32,
+    /// sent in X-Api-Key to pause, drain and resume the game loop, admin routes are disabled if empty
+    pub admin_api_key: String,
+    /// rooms of the server, every room runs its own game loop
+    pub rooms: Vec<RoomSettings>,
 }
 
 impl EnvSettings {
     pub fn new() -> Self {
-        Self {
+        let mut env_settings = Self {
             user_jwt_expiration_minutes: env::var("JWT_EXPIRATION_MINUTES")
                 .expect("JWT_EXPIRATION_MINUTES in .env file is missing")
                 .parse::<i64>()
@@ -30,6 +87,133 @@ impl EnvSettings {
                 .expect("HOUSE_EDGE_PERCENT in .env file is missing")
                 .parse::<f32>()
                 .expect("HOUSE_EDGE_PERCENT must be a valid f32 number"),
+            multiplier_growth_rate: env::var("MULTIPLIER_GROWTH_RATE")
+                .expect("MULTIPLIER_GROWTH_RATE in .env file is missing")
+                .parse::<f64>()
+                .ok()
+                .filter(|rate| rate.is_finite() && *rate > 0.0)
+                .expect("MULTIPLIER_GROWTH_RATE must be a valid f64 number greater than 0"),
+            game_tick_interval_ms: env::var("GAME_TICK_INTERVAL_MS")
+                .expect("GAME_TICK_INTERVAL_MS in .env file is missing")
+                .parse::<u64>()
+                .ok()
+                .filter(|interval| *interval > 0)
+                .expect("GAME_TICK_INTERVAL_MS must be a valid u64 number greater than 0"),
+            seed_chain_length: env::var("SEED_CHAIN_LENGTH")
+                .expect("SEED_CHAIN_LENGTH in .env file is missing")
+                .parse::<u32>()
+                .ok()
+                .filter(|length| *length > 0)
+                .expect("SEED_CHAIN_LENGTH must be a valid u32 number greater than 0"),
+            seed_chain_state_file: env::var("SEED_CHAIN_STATE_FILE")
+                .expect("SEED_CHAIN_STATE_FILE in .env file is missing"),
+            client_seed_salt: env::var("CLIENT_SEED_SALT")
+                .expect("CLIENT_SEED_SALT in .env file is missing"),
+            round_history_size: env::var("ROUND_HISTORY_SIZE")
+                .expect("ROUND_HISTORY_SIZE in .env file is missing")
+                .parse::<usize>()
+                .expect("ROUND_HISTORY_SIZE must be a valid usize number"),
+            round_history_file: env::var("ROUND_HISTORY_FILE")
+                .expect("ROUND_HISTORY_FILE in .env file is missing"),
+            balance_store_type: env::var("BALANCE_STORE")
+                .expect("BALANCE_STORE in .env file is missing")
+                .parse::<BalanceStoreType>()
+                .expect("BALANCE_STORE must be either memory or file"),
+            balance_store_file: env::var("BALANCE_STORE_FILE")
+                .expect("BALANCE_STORE_FILE in .env file is missing"),
+            ledger_file: env::var("LEDGER_FILE").expect("LEDGER_FILE in .env file is missing"),
+            wallet_url: env::var("WALLET_URL").expect("WALLET_URL in .env file is missing"),
+            wallet_api_key: env::var("WALLET_API_KEY")
+                .expect("WALLET_API_KEY in .env file is missing"),
+            wallet_timeout_ms: env::var("WALLET_TIMEOUT_MS")
+                .expect("WALLET_TIMEOUT_MS in .env file is missing")
+                .parse::<u64>()
+                .expect("WALLET_TIMEOUT_MS must be a valid u64 number"),
+            fun_currency: env::var("FUN_CURRENCY").expect("FUN_CURRENCY in .env file is missing"),
+            real_currency: env::var("REAL_CURRENCY")
+                .expect("REAL_CURRENCY in .env file is missing"),
+            bet_limits: BetLimits::new(
+                env::var("MIN_BET")
+                    .expect("MIN_BET in .env file is missing")
+                    .parse::<u64>()
+                    .expect("MIN_BET must be a valid u64 number"),
+                env::var("MAX_BET")
+                    .expect("MAX_BET in .env file is missing")
+                    .parse::<u64>()
+                    .expect("MAX_BET must be a valid u64 number"),
+                env::var("MAX_WIN")
+                    .expect("MAX_WIN in .env file is missing")
+                    .parse::<u64>()
+                    .expect("MAX_WIN must be a valid u64 number"),
+            )
+            .expect("MIN_BET, MAX_BET and MAX_WIN must be 0 < MIN_BET <= MAX_BET <= MAX_WIN"),
+            currency_bet_limits: parse_currency_bet_limits(
+                &env::var("CURRENCY_BET_LIMITS")
+                    .expect("CURRENCY_BET_LIMITS in .env file is missing"),
+            )
+            .expect("CURRENCY_BET_LIMITS must be a list of currency:min_bet:max_bet:max_win"),
+            max_round_liability: env::var("MAX_ROUND_LIABILITY")
+                .expect("MAX_ROUND_LIABILITY in .env file is missing")
+                .parse::<u64>()
+                .expect("MAX_ROUND_LIABILITY must be a valid u64 number"),
+            fun_refill_amount: env::var("FUN_REFILL_AMOUNT")
+                .expect("FUN_REFILL_AMOUNT in .env file is missing")
+                .parse::<u64>()
+                .expect("FUN_REFILL_AMOUNT must be a valid u64 number"),
+            fun_refill_cooldown_secs: env::var("FUN_REFILL_COOLDOWN_SECONDS")
+                .expect("FUN_REFILL_COOLDOWN_SECONDS in .env file is missing")
+                .parse::<u64>()
+                .expect("FUN_REFILL_COOLDOWN_SECONDS must be a valid u64 number"),
+            round_cooldown_ms: env::var("ROUND_COOLDOWN_MS")
+                .expect("ROUND_COOLDOWN_MS in .env file is missing")
+                .parse::<u64>()
+                .expect("ROUND_COOLDOWN_MS must be a valid u64 number"),
+            min_round_players: env::var("MIN_ROUND_PLAYERS")
+                .expect("MIN_ROUND_PLAYERS in .env file is missing")
+                .parse::<u32>()
+                .expect("MIN_ROUND_PLAYERS must be a valid u32 number"),
+            min_round_bets: env::var("MIN_ROUND_BETS")
+                .expect("MIN_ROUND_BETS in .env file is missing")
+                .parse::<u32>()
+                .expect("MIN_ROUND_BETS must be a valid u32 number"),
+            admin_api_key: env::var("ADMIN_API_KEY").expect("ADMIN_API_KEY in .env file is missing"),
+            rooms: parse_rooms(&env::var("ROOMS").expect("ROOMS in .env file is missing")).expect(
+                "ROOMS must be a list of id:currency:betting_time_duration:house_edge:min_bet:max_bet:max_win",
+            ),
+        };
+        if env_settings.rooms.is_empty() {
+            env_settings.rooms = env_settings.default_rooms();
+        }
+        env_settings
+    }
+
+    /// A room for each play mode, with the game configs above
+    fn default_rooms(&self) -> Vec<RoomSettings> {
+        [("fun", &self.fun_currency), ("real", &self.real_currency)]
+            .into_iter()
+            .map(|(id, currency)| RoomSettings {
+                id: id.to_string(),
+                currency: currency.clone(),
+                betting_time_duration: self.betting_time_duration,
+                house_edge_pct: self.house_edge_pct,
+                bet_limits: self.bet_limits_of(currency),
+            })
+            .collect()
+    }
+
+    /// Bet limits of the currency
+    pub fn bet_limits_of(&self, currency: &str) -> BetLimits {
+        self.currency_bet_limits
+            .get(currency)
+            .copied()
+            .unwrap_or(self.bet_limits)
+    }
+
+    /// Currency of balances in the play mode
+    pub fn currency_of(&self, play_mode: PlayMode) -> &str {
+        match play_mode {
+            PlayMode::FUN => &self.fun_currency,
+            PlayMode::REAL => &self.real_currency,
         }
     }
 }
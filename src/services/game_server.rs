@@ -1,18 +1,30 @@
-use actix::{Actor, AsyncContext, Context, Handler, Recipient};
+use actix::{
+    Actor, ActorFutureExt, AsyncContext, Context, ContextFutureSpawner, Handler, Recipient,
+    WrapFuture,
+};
+use actix_web::web;
+use chrono::{DateTime, Utc};
 use log::{info, warn};
 use rand::{rngs::ThreadRng, Rng};
-use std::{collections::HashMap, sync::{atomic::Ordering, Arc, Mutex}};
+use std::{cmp::Reverse, collections::HashMap, sync::{atomic::Ordering, Arc, Mutex}, time::Duration};
 
-use crate::services::{crash_game::GameState, generate_username::generate_guest_username};
+use crate::{routes::auth::PlayMode, services::generate_username::generate_guest_username};
 
 use super::{
-    balance_system::BalanceSystem,
+    balance_system::{transaction_id, BalanceError, BalanceSystem, TransactionKind},
     crash_game::CrashGame,
+    crash_game_math::sha256,
     env_settings::EnvSettings,
-    game_stats::GameStats,
+    game_stats::{GameLoopState, GameStats},
+    round_history::{RoundHistory, RoundRecord},
+    room::{RoomRegistry, RoomSettings},
+    round_phase::{RoundPhase, RoundStateMachine},
+    seed_chain::SeedChain,
     message_types::{
-        BetRequest, BettingTimerStarted, BettingTimerUpdate, Connect, CrashOutRequest, Disconnect,
-        GameError, GameEvent, GameFinished, GameRoundUpdate, GameStarted, PlayerJoined,
+        BetRequest, BettingTimerFinished, BettingTimerStarted, BettingTimerUpdate,
+        ChangeGameLoopState, Connect, CrashOutReason, CrashOutRequest, Disconnect,
+        ErrorCode, GameError, GameEvent, GameFinished, GameRoundUpdate, GameStarted, PlayerJoined,
+        RefillBalanceRequest, RoundResult, RoundWinner, SetClientSeed,
     },
 };
 
@@ -20,17 +32,94 @@ use super::{
 pub struct GameServer {
     peers: HashMap<String, PeerInfo>,
     session_to_uuid: HashMap<usize, String>,
-    bet_map: HashMap<String, u64>,
+    rooms: HashMap<String, GameRoom>,
+    room_registry: RoomRegistry,
     rng: ThreadRng,
     game_stats: GameStats,
-    crash_game: CrashGame,
     balance_system: BalanceSystem,
+    round_history: RoundHistory,
+    env_settings: EnvSettings,
+    /// wins, that couldn't be credited yet
+    failed_payouts: Vec<Payout>,
 }
 
+/// Table with its own game loop and bets, players only get the events of their room
+#[derive(Debug)]
+struct GameRoom {
+    settings: RoomSettings,
+    crash_game: CrashGame,
+    round_phase: RoundStateMachine,
+    bet_map: HashMap<String, Bet>,
+    round_stats: RoundStats,
+}
+
+/// number of crash points sent to players on join
+const RECENT_CRASH_POINTS_COUNT: usize = 20;
+
+/// number of the biggest wins sent in the round summary
+const ROUND_SUMMARY_WINNERS_COUNT: usize = 20;
+
+/// how often failed payouts are retried
+const PAYOUT_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
 #[derive(Debug)]
 struct PeerInfo {
+    /// session the player joined with, the latest one if the player joined again
+    session_id: usize,
     addr: Recipient<GameEvent>,
     display_name: String,
+    client_seed: String,
+    play_mode: PlayMode,
+    room_id: String,
+    /// false until the join response is sent, room events are sent only after it
+    ready: bool,
+}
+
+#[derive(Debug)]
+struct Bet {
+    amount: u64,
+    /// multiplier at which the bet is crashed out automatically
+    auto_crash_out_multiplier: Option<u32>,
+    /// seed of the player at the time the bet was placed
+    client_seed: String,
+    /// win amount is capped at this amount
+    max_win: u64,
+}
+
+impl Bet {
+    fn win_amount(&self, multiplier: u32) -> u64 {
+        let win_amount = (self.amount as u128 * multiplier as u128 / 100) as u64;
+        win_amount.min(self.max_win)
+    }
+}
+
+/// Win of a crashed out bet, it's counted in the round once it's credited
+#[derive(Debug)]
+struct Payout {
+    room_id: String,
+    round_id: u32,
+    uuid: String,
+    bet_amount: u64,
+    win_amount: u64,
+    multiplier: u32,
+    reason: CrashOutReason,
+    /// player is told about the failed credit only once
+    is_retry: bool,
+}
+
+/// Data of the current round, recorded in the round history when the round is finished
+#[derive(Debug, Default)]
+struct RoundStats {
+    round_id: u32,
+    seed_chain_hash: String,
+    started_at: Option<DateTime<Utc>>,
+    /// final multiplier, 0 until the round crashed
+    crash_point: u32,
+    total_wagered: u64,
+    total_paid_out: u64,
+    player_count: u32,
+    /// bets, that were crashed out before the crash
+    winners: Vec<RoundWinner>,
 }
 
 impl GameServer {
@@ -38,35 +127,402 @@ impl GameServer {
         game_stats: GameStats,
         env_settings: EnvSettings,
         balance_system: BalanceSystem,
+        seed_chain: SeedChain,
+        round_history: RoundHistory,
+        room_registry: RoomRegistry,
     ) -> Self {
+        // rooms share the seed chain, so round ids are unique across rooms
+        let rooms = room_registry
+            .list()
+            .into_iter()
+            .map(|(settings, _)| {
+                let crash_game = CrashGame::new(
+                    settings.id.clone(),
+                    settings.betting_time_duration,
+                    settings.house_edge_pct,
+                    env_settings.multiplier_growth_rate,
+                    env_settings.game_tick_interval_ms,
+                    seed_chain.clone(),
+                    env_settings.client_seed_salt.clone(),
+                );
+                let room = GameRoom {
+                    settings,
+                    crash_game,
+                    round_phase: RoundStateMachine::new(),
+                    bet_map: HashMap::new(),
+                    round_stats: RoundStats::default(),
+                };
+                (room.settings.id.clone(), room)
+            })
+            .collect();
+
         Self {
             peers: HashMap::new(),
             session_to_uuid: HashMap::new(),
-            bet_map: HashMap::new(),
+            rooms,
+            room_registry,
             rng: rand::thread_rng(),
             game_stats: game_stats,
-            crash_game: CrashGame::new(
-                env_settings.betting_time_duration,
-                env_settings.house_edge_pct,
-            ),
             balance_system: balance_system,
+            round_history,
+            env_settings,
+            failed_payouts: Vec::new(),
         }
     }
 
-    fn broadcast(&self, event: GameEvent, exclude_uuid: Option<&str>) {
+    /// Sends the event to the players in the room
+    fn broadcast(&self, room_id: &str, event: GameEvent, exclude_uuid: Option<&str>) {
         for (uuid, peer) in &self.peers {
-            if Some(uuid.as_str()) != exclude_uuid {
+            if peer.ready && peer.room_id == room_id && Some(uuid.as_str()) != exclude_uuid {
                 peer.addr.do_send(event.clone());
             }
         }
     }
+
+    /// Moves the round of the room to the next phase, and tells the players about it.
+    /// Returns false if the round can't move to the phase.
+    fn change_phase(&mut self, room_id: &str, phase: RoundPhase) -> bool {
+        let Some(room) = self.rooms.get_mut(room_id) else {
+            return false;
+        };
+        if let Err(err) = room.round_phase.change_to(phase) {
+            warn!(
+                "invalid round phase change in room {:?}! {:?}",
+                room_id, err
+            );
+            return false;
+        }
+        info!("round phase of room {:?}: {:?}", room_id, phase);
+
+        let crash_point = room.round_stats.crash_point;
+        let active_rounds = self
+            .rooms
+            .values()
+            .filter(|room| room.round_phase.phase() != RoundPhase::Idle)
+            .count() as u32;
+        self.game_stats
+            .active_rounds
+            .store(active_rounds, Ordering::SeqCst);
+        self.broadcast(
+            room_id,
+            GameEvent::RoundPhaseChanged {
+                phase: phase.into(),
+                crash_point,
+            },
+            None,
+        );
+        true
+    }
+
+    /// Starts the betting phase of the next round in the room
+    fn start_betting(&mut self, room_id: &str) {
+        if let Some(room) = self.rooms.get_mut(room_id) {
+            room.round_stats = RoundStats::default();
+        }
+        if !self.change_phase(room_id, RoundPhase::Betting) {
+            return;
+        }
+        let Some(room) = self.rooms.get_mut(room_id) else {
+            return;
+        };
+        // round can't be played without a server seed, that is saved as used
+        if let Err(err) = room.crash_game.start_betting_timer() {
+            warn!("unable to start a round in room {:?}! {:?}", room_id, err);
+            self.broadcast(room_id, GameEvent::GameError {}, None);
+            self.change_phase(room_id, RoundPhase::Idle);
+        }
+    }
+
+    /// Ends the betting phase of the room without launching the round, bets are released
+    fn cancel_betting(&mut self, room_id: &str) {
+        let Some(room) = self.rooms.get_mut(room_id) else {
+            return;
+        };
+        room.crash_game.stop_betting_timer();
+        let round_id = room.round_stats.round_id;
+        for (uuid, _) in room.bet_map.drain() {
+            if let Err(err) = self
+                .balance_system
+                .release_reserved_bet_amount(&uuid, round_id)
+            {
+                warn!("unable to release the bet of {:?}! {:?}", uuid, err);
+            }
+        }
+        room.update_client_seed_inputs();
+        self.change_phase(room_id, RoundPhase::Idle);
+    }
+
+    /// Player, who joined in the session, with the room the player is in
+    fn player_of(&self, session_id: usize) -> Option<(String, String)> {
+        let uuid = self.session_to_uuid.get(&session_id)?;
+        let peer = self.peers.get(uuid)?;
+        Some((uuid.clone(), peer.room_id.clone()))
+    }
+
+    /// Runs balance operations on the blocking thread pool, as they may call the wallet of
+    /// REAL mode players. The result is handed back to the actor, when the operations are done.
+    fn spawn_balance_task<T, F, C>(&self, ctx: &mut Context<Self>, task: F, on_done: C)
+    where
+        T: Send + 'static,
+        F: FnOnce(&BalanceSystem) -> T + Send + 'static,
+        C: FnOnce(&mut Self, T) + 'static,
+    {
+        let balance_system = self.balance_system.clone();
+        web::block(move || task(&balance_system))
+            .into_actor(self)
+            .map(|result, act, _| match result {
+                Ok(result) => on_done(act, result),
+                Err(err) => warn!("balance task failed! {:?}", err),
+            })
+            .spawn(ctx);
+    }
+
+    /// Sends the join response, once the balance of the player is loaded
+    fn finish_join(&mut self, session_id: usize, uuid: &str, balance: u64, players_online: u32) {
+        // player left, or joined again in the meantime
+        let Some(peer) = self
+            .peers
+            .get_mut(uuid)
+            .filter(|peer| peer.session_id == session_id)
+        else {
+            return;
+        };
+        let Some(room) = self.rooms.get(&peer.room_id) else {
+            return;
+        };
+        peer.ready = true;
+
+        let game_data = room.crash_game.get_game_data();
+        let round_phase = room.round_phase.phase();
+        let room_id = room.settings.id.clone();
+        peer.addr.do_send(GameEvent::PlayerJoinedResponse {
+            betting_time_left_ms: game_data.betting_time_left_ms,
+            game_state: round_phase.into(),
+            multiplier: game_data.multiplier,
+            round_time_elapsed_ms: game_data.round_time_elapsed_ms,
+            multiplier_growth_rate: game_data.multiplier_growth_rate,
+            display_name: peer.display_name.clone(),
+            balance,
+            recent_crash_points: self
+                .round_history
+                .recent_crash_points(&room_id, RECENT_CRASH_POINTS_COUNT),
+            currency: room.settings.currency.clone(),
+            bet_limits: room.settings.bet_limits,
+            room_id: room_id.clone(),
+        });
+
+        let game_loop_state = self.game_stats.game_loop_state();
+        if game_loop_state != GameLoopState::Running {
+            peer.addr.do_send(GameEvent::GameLoopStateChanged {
+                state: game_loop_state.into(),
+            });
+        }
+
+        let display_name = peer.display_name.clone();
+        self.broadcast(
+            &room_id,
+            GameEvent::RemotePlayerJoined {
+                display_name,
+                players_online,
+            },
+            Some(uuid),
+        );
+
+        if game_loop_state == GameLoopState::Running && round_phase == RoundPhase::Idle {
+            self.start_betting(&room_id);
+        }
+    }
+
+    /// Starts the round, once the bets of the room are committed
+    fn start_round(&mut self, room_id: &str, failed_bets: Vec<String>) {
+        let Some(room) = self.rooms.get_mut(room_id) else {
+            return;
+        };
+        // bets, that couldn't be paid, don't take part in the round
+        for uuid in failed_bets {
+            room.bet_map.remove(&uuid);
+        }
+        room.update_client_seed_inputs();
+        room.crash_game.start_game();
+    }
+
+    /// Settles the active bet of the player at the given multiplier. The win is paid out
+    /// in the background.
+    fn crash_out(
+        &mut self,
+        ctx: &mut Context<Self>,
+        room_id: &str,
+        uuid: &str,
+        multiplier: u32,
+        reason: CrashOutReason,
+    ) {
+        let Some(room) = self.rooms.get_mut(room_id) else {
+            return;
+        };
+        let Some(bet) = room.bet_map.remove(uuid) else {
+            return;
+        };
+        // auto crash out target, that is already reached, takes precedence
+        let (multiplier, reason) = match bet.auto_crash_out_multiplier {
+            Some(target) if target <= multiplier => (target, CrashOutReason::AutoCrashOut),
+            _ => (multiplier, reason),
+        };
+        let win_amount = bet.win_amount(multiplier);
+        info!(
+            "player crashed out! {:?}, multiplier: {:?}, winAmount: {:?}, reason: {:?}",
+            uuid, multiplier, win_amount, reason
+        );
+
+        let payout = Payout {
+            room_id: room_id.to_string(),
+            round_id: room.round_stats.round_id,
+            uuid: uuid.to_string(),
+            bet_amount: bet.amount,
+            win_amount,
+            multiplier,
+            reason,
+            is_retry: false,
+        };
+        self.pay_out(ctx, payout);
+    }
+
+    /// Credits the win to the balance of the player. Credits, that failed because the wallet
+    /// is unavailable, are retried with the same transaction id.
+    fn pay_out(&mut self, ctx: &mut Context<Self>, payout: Payout) {
+        let tx_id = transaction_id(payout.round_id, &payout.uuid, TransactionKind::Win);
+        let (uuid, win_amount, round_id) =
+            (payout.uuid.clone(), payout.win_amount, payout.round_id);
+        self.spawn_balance_task(
+            ctx,
+            move |balance_system| balance_system.add(&uuid, win_amount, round_id, &tx_id),
+            move |act, result| match result {
+                Ok(balance) => act.finish_payout(payout, balance),
+                Err(BalanceError::WalletUnavailable) => {
+                    warn!(
+                        "unable to pay out {:?} to {:?}, retrying later!",
+                        payout.win_amount, payout.uuid
+                    );
+                    if !payout.is_retry {
+                        if let Some(peer) = act.peers.get(&payout.uuid) {
+                            peer.addr.do_send(GameEvent::CrashOutError {
+                                code: ErrorCode::WalletUnavailable.into(),
+                            });
+                        }
+                    }
+                    act.failed_payouts.push(Payout {
+                        is_retry: true,
+                        ..payout
+                    });
+                }
+                Err(err) => warn!(
+                    "unable to pay out {:?} to {:?}! {:?}",
+                    payout.win_amount, payout.uuid, err
+                ),
+            },
+        );
+    }
+
+    /// Counts the credited win in the round, and tells the players about it
+    fn finish_payout(&mut self, payout: Payout, balance: u64) {
+        let display_name = self
+            .peers
+            .get(&payout.uuid)
+            .map_or(String::new(), |peer| peer.display_name.clone());
+        // round may be over, if the win was credited on a retry
+        if let Some(room) = self
+            .rooms
+            .get_mut(&payout.room_id)
+            .filter(|room| room.round_stats.round_id == payout.round_id)
+        {
+            room.round_stats.total_paid_out += payout.win_amount;
+            room.round_stats.winners.push(RoundWinner {
+                display_name: display_name.clone(),
+                bet_amount: payout.bet_amount,
+                win_amount: payout.win_amount,
+                multiplier: payout.multiplier,
+            });
+        }
+
+        if let Some(peer) = self.peers.get(&payout.uuid) {
+            peer.addr.do_send(GameEvent::CrashOutResponse {
+                win_amount: payout.win_amount,
+                multiplier: payout.multiplier,
+                balance,
+                reason: payout.reason.into(),
+            });
+
+            self.broadcast(
+                &payout.room_id,
+                GameEvent::RemotePlayerCrashOut {
+                    display_name,
+                    win_amount: payout.win_amount,
+                },
+                Some(&payout.uuid),
+            );
+        }
+    }
+}
+
+impl GameRoom {
+    /// Error code, if the bet amount is outside of the bet limits of the room
+    fn check_bet_limits(&self, bet_amount: u64) -> Option<ErrorCode> {
+        let limits = self.settings.bet_limits;
+        if bet_amount < limits.min_bet {
+            Some(ErrorCode::BetBelowMin)
+        } else if bet_amount > limits.max_bet {
+            Some(ErrorCode::BetAboveMax)
+        } else {
+            None
+        }
+    }
+
+    /// Lowest multiplier, at which the stakes of the open bets times the multiplier reach the cap.
+    /// Bets, that reached their max win, are already crashed out.
+    fn liability_cap_multiplier(&self, max_round_liability: u64) -> u32 {
+        let total_amount: u64 = self.bet_map.values().map(|bet| bet.amount).sum();
+        if total_amount == 0 {
+            return u32::MAX;
+        }
+        let multiplier = (max_round_liability as u128 * 100).div_ceil(total_amount as u128);
+        multiplier.clamp(100, u32::MAX as u128) as u32
+    }
+
+    /// Client seed of the round is derived from the seeds of players, who placed bets.
+    fn update_client_seed_inputs(&self) {
+        let client_seed_inputs = self
+            .bet_map
+            .values()
+            .filter(|bet| !bet.client_seed.is_empty())
+            .map(|bet| bet.client_seed.clone())
+            .collect();
+        self.crash_game.set_client_seed_inputs(client_seed_inputs);
+    }
+}
+
+/// Error code sent to the player, when a balance operation failed
+fn balance_error_code(err: BalanceError) -> ErrorCode {
+    match err {
+        BalanceError::UnknownPlayer => ErrorCode::NotJoined,
+        BalanceError::InsufficientFunds | BalanceError::Overflow => ErrorCode::InsufficientBalance,
+        BalanceError::WalletUnavailable => ErrorCode::WalletUnavailable,
+        BalanceError::RefillNotAllowed => ErrorCode::RefillNotAllowed,
+        BalanceError::RefillCooldown => ErrorCode::RefillCooldown,
+    }
 }
 
 impl Actor for GameServer {
     type Context = Context<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
-        self.crash_game.set_game_server_addr(ctx.address());
+        for room in self.rooms.values_mut() {
+            room.crash_game.set_game_server_addr(ctx.address());
+        }
+
+        ctx.run_interval(PAYOUT_RETRY_INTERVAL, |act, ctx| {
+            for payout in std::mem::take(&mut act.failed_payouts) {
+                act.pay_out(ctx, payout);
+            }
+        });
     }
 }
 
@@ -85,14 +541,35 @@ impl Handler<Disconnect> for GameServer {
     fn handle(&mut self, msg: Disconnect, _: &mut Self::Context) -> Self::Result {
         info!("peer disconnected!");
 
-        let players_online = self
-            .game_stats
-            .players_online
-            .fetch_sub(1, Ordering::SeqCst);
-
         if let Some(uuid) = self.session_to_uuid.remove(&msg.session_id) {
+            // player is in the room with a newer session
+            if self.peers.get(&uuid).map(|peer| peer.session_id) != Some(msg.session_id) {
+                return;
+            }
             if let Some(peer) = self.peers.remove(&uuid) {
+                // players are counted once, no matter how many times they joined
+                self.game_stats
+                    .players_online
+                    .fetch_sub(1, Ordering::SeqCst);
+
+                // bets placed in the current betting phase are released, active bets stay in the round
+                if let Some(room) = self.rooms.get_mut(&peer.room_id) {
+                    if room.round_phase.phase() == RoundPhase::Betting
+                        && room.bet_map.remove(&uuid).is_some()
+                    {
+                        if let Err(err) = self
+                            .balance_system
+                            .release_reserved_bet_amount(&uuid, room.round_stats.round_id)
+                        {
+                            warn!("unable to release the bet of {:?}! {:?}", uuid, err);
+                        }
+                        room.update_client_seed_inputs();
+                    }
+                }
+
+                let players_online = self.room_registry.player_left(&peer.room_id);
                 self.broadcast(
+                    &peer.room_id,
                     GameEvent::RemotePlayerLeft {
                         display_name: peer.display_name,
                         players_online,
@@ -107,49 +584,109 @@ impl Handler<Disconnect> for GameServer {
 impl Handler<PlayerJoined> for GameServer {
     type Result = ();
 
-    fn handle(&mut self, msg: PlayerJoined, _: &mut Self::Context) -> Self::Result {
-        info!("peer joined the game! {:?}", msg.uuid);
+    fn handle(&mut self, msg: PlayerJoined, ctx: &mut Self::Context) -> Self::Result {
+        info!("peer joined the game! {:?} {:?}", msg.uuid, msg.room_id);
+
+        let currency = self.env_settings.currency_of(msg.play_mode).to_string();
+        let room_id = if msg.room_id.is_empty() {
+            self.room_registry
+                .default_room_of(&currency)
+                .map_or(String::new(), |settings| settings.id.clone())
+        } else {
+            msg.room_id.clone()
+        };
+        let Some(room) = self.rooms.get(&room_id) else {
+            warn!("unknown room {:?} {:?}", room_id, msg.uuid);
+            msg.peer_addr.do_send(GameEvent::JoinGameError {
+                code: ErrorCode::UnknownRoom.into(),
+            });
+            return;
+        };
+        if room.settings.currency != currency {
+            warn!(
+                "room {:?} doesn't accept {:?} {:?}",
+                room_id, currency, msg.uuid
+            );
+            msg.peer_addr.do_send(GameEvent::JoinGameError {
+                code: ErrorCode::RoomCurrencyNotAccepted.into(),
+            });
+            return;
+        }
+
+        // player joins another room, e.g. from another tab. The balance has a single reservation,
+        // so a bet can't be pending in two rooms.
+        if let Some(previous_peer) = self.peers.get(&msg.uuid) {
+            if let Some(previous_room) = self
+                .rooms
+                .get_mut(&previous_peer.room_id)
+                .filter(|previous_room| previous_room.settings.id != room_id)
+            {
+                if previous_room.bet_map.contains_key(&msg.uuid) {
+                    // bet is committed, or about to be, it's settled in the previous room
+                    if previous_room.round_phase.phase() != RoundPhase::Betting {
+                        warn!(
+                            "player has an active bet in room {:?} {:?}",
+                            previous_room.settings.id, msg.uuid
+                        );
+                        msg.peer_addr.do_send(GameEvent::JoinGameError {
+                            code: ErrorCode::ActiveBetInAnotherRoom.into(),
+                        });
+                        return;
+                    }
+
+                    previous_room.bet_map.remove(&msg.uuid);
+                    if let Err(err) = self
+                        .balance_system
+                        .release_reserved_bet_amount(&msg.uuid, previous_room.round_stats.round_id)
+                    {
+                        warn!("unable to release the bet of {:?}! {:?}", msg.uuid, err);
+                    }
+                    previous_room.update_client_seed_inputs();
+                }
+            }
+        }
 
         let display_name = generate_guest_username();
 
         let peer_info = PeerInfo {
+            session_id: msg.session_id,
             addr: msg.peer_addr.clone(),
             display_name: display_name.clone(),
+            client_seed: msg.client_seed,
+            play_mode: msg.play_mode,
+            room_id: room_id.clone(),
+            ready: false,
         };
 
-        self.peers.insert(msg.uuid.clone(), peer_info);
+        // player joined again, e.g. from another tab, the previous session can't act for the player anymore
+        if let Some(previous_peer) = self.peers.insert(msg.uuid.clone(), peer_info) {
+            self.session_to_uuid.remove(&previous_peer.session_id);
+            self.room_registry.player_left(&previous_peer.room_id);
+        } else {
+            self.game_stats
+                .players_online
+                .fetch_add(1, Ordering::SeqCst);
+        }
         self.session_to_uuid
             .insert(msg.session_id, msg.uuid.clone());
+        let players_online = self.room_registry.player_joined(&room_id);
 
-        let players_online = self
-            .game_stats
-            .players_online
-            .fetch_add(1, Ordering::SeqCst);
-
-        self.balance_system.ensure_balance(msg.uuid.clone());
-
-        let game_data = self.crash_game.get_game_data();
-
-        msg.peer_addr.do_send(GameEvent::PlayerJoinedResponse {
-            betting_time_left_ms: game_data.betting_time_left_ms,
-            game_state: game_data.game_state.into(),
-            multiplier: game_data.multiplier,
-            round_time_elapsed_ms: game_data.round_time_elapsed_ms,
-            display_name: display_name.clone(),
-            balance: self.balance_system.fetch_balance(&msg.uuid),
-        });
-
-        self.broadcast(
-            GameEvent::RemotePlayerJoined {
-                display_name,
-                players_online,
+        // balance of REAL mode players is fetched from the wallet
+        let uuid = msg.uuid.clone();
+        let play_mode = msg.play_mode;
+        self.spawn_balance_task(
+            ctx,
+            move |balance_system| {
+                if let Err(err) = balance_system.ensure_balance(uuid.clone(), play_mode, &currency)
+                {
+                    warn!("unable to fetch the balance of {:?}! {:?}", uuid, err);
+                }
+                balance_system.fetch_balance(&uuid)
+            },
+            move |act, balance| {
+                act.finish_join(msg.session_id, &msg.uuid, balance, players_online);
             },
-            Some(&msg.uuid),
         );
-
-        if matches!(game_data.game_state, GameState::Idle) {
-            self.crash_game.start_betting_timer();
-        }
     }
 }
 
@@ -157,87 +694,200 @@ impl Handler<BetRequest> for GameServer {
     type Result = ();
 
     fn handle(&mut self, msg: BetRequest, _: &mut Self::Context) -> Self::Result {
-        // get uuid from session_id
-        if let Some(uuid) = self.session_to_uuid.get(&msg.session_id) {
-            let game_data = self.crash_game.get_game_data();
+        // get uuid and room from session_id
+        if let Some((uuid, room_id)) = self.player_of(msg.session_id) {
+            let Some(room) = self.rooms.get_mut(&room_id) else {
+                return;
+            };
+            if room.round_phase.phase() == RoundPhase::Betting {
+                let currency = self.balance_system.currency_of(&uuid).unwrap_or_default();
+                if msg.bet_amount > 0 {
+                    if let Some(code) = room.check_bet_limits(msg.bet_amount) {
+                        warn!("bet out of limits! {:?} {:?}", uuid, msg.bet_amount);
+                        msg.peer_addr
+                            .do_send(GameEvent::BetError { code: code.into() });
+                        return;
+                    }
+                }
 
-            if matches!(game_data.game_state, GameState::BettingInProgress) {
-                if !self.balance_system.reserve_bet_amount(uuid, msg.bet_amount) {
+                let round_id = room.round_stats.round_id;
+                let result = if msg.bet_amount > 0 {
+                    self.balance_system
+                        .reserve_bet_amount(&uuid, msg.bet_amount, round_id)
+                } else {
+                    // player cancelled the bet
+                    self.balance_system
+                        .release_reserved_bet_amount(&uuid, round_id)
+                };
+                if let Err(err) = result {
                     // player doesn't have enough balance
-                    // todo
-                    warn!("bets placed! (not enough balance) {:?} {:?}", uuid, msg.bet_amount);
+                    warn!("bets placed! ({:?}) {:?} {:?}", err, uuid, msg.bet_amount);
+                    msg.peer_addr.do_send(GameEvent::BetError {
+                        code: balance_error_code(err).into(),
+                    });
                     return;
                 }
 
                 info!("bets placed! {:?} {:?}", uuid, msg.bet_amount);
                 if msg.bet_amount > 0 {
-                    self.bet_map.insert(uuid.clone(), msg.bet_amount);
+                    let client_seed = self
+                        .peers
+                        .get(&uuid)
+                        .map_or(String::new(), |peer| peer.client_seed.clone());
+                    room.bet_map.insert(
+                        uuid.clone(),
+                        Bet {
+                            amount: msg.bet_amount,
+                            auto_crash_out_multiplier: msg.auto_crash_out_multiplier,
+                            client_seed,
+                            max_win: room.settings.bet_limits.max_win,
+                        },
+                    );
                 } else {
                     // player cancelled the bet
-                    self.bet_map.remove(uuid);
+                    room.bet_map.remove(&uuid);
                 }
+                room.update_client_seed_inputs();
 
-                if let Some(peer) = self.peers.get(uuid) {
+                if let Some(peer) = self.peers.get(&uuid) {
                     peer.addr.do_send(GameEvent::BetResponse {
-                        balance: self.balance_system.fetch_balance(uuid),
+                        balance: self.balance_system.fetch_balance(&uuid),
+                        currency,
                     });
 
                     self.broadcast(
+                        &room_id,
                         GameEvent::RemotePlayerBetsPlaced {
                             display_name: peer.display_name.clone(),
                             bet_amount: msg.bet_amount,
                         },
-                        Some(uuid),
+                        Some(&uuid),
                     );
                 }
             } else {
                 warn!("bets received when state is not in BETTING_IN_PROGRESS");
+                msg.peer_addr.do_send(GameEvent::BetError {
+                    code: ErrorCode::BettingClosed.into(),
+                });
             }
         } else {
             warn!("BetRequest: unknown session id {:?}", msg.session_id);
+            msg.peer_addr.do_send(GameEvent::BetError {
+                code: ErrorCode::NotJoined.into(),
+            });
         }
     }
 }
 
-impl Handler<CrashOutRequest> for GameServer {
+impl Handler<RefillBalanceRequest> for GameServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: RefillBalanceRequest, _: &mut Self::Context) -> Self::Result {
+        let Some(uuid) = self.session_to_uuid.get(&msg.session_id) else {
+            warn!(
+                "RefillBalanceRequest: unknown session id {:?}",
+                msg.session_id
+            );
+            msg.peer_addr.do_send(GameEvent::RefillBalanceError {
+                code: ErrorCode::NotJoined.into(),
+            });
+            return;
+        };
+        if !self
+            .peers
+            .get(uuid)
+            .is_some_and(|peer| peer.play_mode == PlayMode::FUN)
+        {
+            warn!("refill requested in REAL mode! {:?}", uuid);
+            msg.peer_addr.do_send(GameEvent::RefillBalanceError {
+                code: ErrorCode::RefillNotAllowed.into(),
+            });
+            return;
+        }
+
+        let result = self.balance_system.refill(
+            uuid,
+            self.env_settings.fun_refill_amount,
+            Duration::from_secs(self.env_settings.fun_refill_cooldown_secs),
+        );
+        match result {
+            Ok(balance) => {
+                info!("balance refilled! {:?} {:?}", uuid, balance);
+                msg.peer_addr
+                    .do_send(GameEvent::RefillBalanceResponse { balance });
+            }
+            Err(err) => {
+                warn!("unable to refill balance! ({:?}) {:?}", err, uuid);
+                msg.peer_addr.do_send(GameEvent::RefillBalanceError {
+                    code: balance_error_code(err).into(),
+                });
+            }
+        }
+    }
+}
+
+impl Handler<SetClientSeed> for GameServer {
     type Result = ();
 
-    fn handle(&mut self, msg: CrashOutRequest, _: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: SetClientSeed, _: &mut Self::Context) -> Self::Result {
         if let Some(uuid) = self.session_to_uuid.get(&msg.session_id) {
-            let game_data = self.crash_game.get_game_data();
-
-            if matches!(game_data.game_state, GameState::GameInProgress) {
-                if let Some(bet_amount) = self.bet_map.remove(uuid) {
-                    let win_amount = (bet_amount as f64 * (game_data.multiplier as f32 / 100.0) as f64) as u64;
-                    info!(
-                        "player crashed out! {:?}, winAmount: {:?}",
-                        uuid, win_amount
-                    );
+            if let Some(peer) = self.peers.get_mut(uuid) {
+                // used for the bets placed from now on
+                peer.client_seed = msg.client_seed;
+            }
+        } else {
+            warn!("SetClientSeed: unknown session id {:?}", msg.session_id);
+        }
+    }
+}
 
-                    if let Some(peer) = self.peers.get(uuid) {
-                        // todo: handle error
-                        self.balance_system.add(uuid.as_str(), win_amount);
+impl Handler<CrashOutRequest> for GameServer {
+    type Result = ();
 
-                        peer.addr.do_send(GameEvent::CrashOutResponse {
-                            win_amount,
-                            multiplier: game_data.multiplier,
-                            balance: self.balance_system.fetch_balance(uuid),
-                        });
+    fn handle(&mut self, msg: CrashOutRequest, ctx: &mut Self::Context) -> Self::Result {
+        if let Some((uuid, room_id)) = self.player_of(msg.session_id) {
+            let Some(room) = self.rooms.get(&room_id) else {
+                return;
+            };
+            let round_phase = room.round_phase.phase();
 
-                        self.broadcast(
-                            GameEvent::RemotePlayerCrashOut {
-                                display_name: peer.display_name.clone(),
-                                win_amount,
-                            },
-                            Some(uuid),
-                        );
+            if round_phase == RoundPhase::Running {
+                // cash-out is resolved at the moment the request arrived, not at the last tick
+                let multiplier = match room.crash_game.get_multiplier_at(msg.requested_at) {
+                    Some(multiplier) => multiplier,
+                    None => {
+                        warn!("crashOut received at or past the crash point {:?}", uuid);
+                        msg.peer_addr.do_send(GameEvent::CrashOutError {
+                            code: ErrorCode::RoundAlreadyCrashed.into(),
+                        });
+                        return;
                     }
+                };
+
+                if !room.bet_map.contains_key(&uuid) {
+                    warn!("crashOut received without an active bet {:?}", uuid);
+                    msg.peer_addr.do_send(GameEvent::CrashOutError {
+                        code: ErrorCode::NoActiveBet.into(),
+                    });
+                    return;
                 }
+
+                self.crash_out(ctx, &room_id, &uuid, multiplier, CrashOutReason::Manual);
             } else {
-                warn!("crashOut received when state is not in GAME_IN_PROGRESS");
+                warn!("crashOut received when round is not running");
+                // bets placed in the current betting phase are not active, until the round starts
+                let code = match round_phase {
+                    RoundPhase::Betting | RoundPhase::Launching => ErrorCode::NoActiveBet,
+                    _ => ErrorCode::RoundAlreadyCrashed,
+                };
+                msg.peer_addr
+                    .do_send(GameEvent::CrashOutError { code: code.into() });
             }
         } else {
             warn!("CrashOutRequest: unknown session id {:?}", msg.session_id);
+            msg.peer_addr.do_send(GameEvent::CrashOutError {
+                code: ErrorCode::NotJoined.into(),
+            });
         }
     }
 }
@@ -249,12 +899,21 @@ impl Handler<BettingTimerStarted> for GameServer {
     type Result = ();
 
     fn handle(&mut self, msg: BettingTimerStarted, _: &mut Self::Context) -> Self::Result {
+        if let Some(room) = self.rooms.get_mut(&msg.room_id) {
+            room.round_stats = RoundStats {
+                round_id: msg.round_id,
+                seed_chain_hash: msg.seed_chain_hash.clone(),
+                ..Default::default()
+            };
+        }
+
         self.broadcast(
+            &msg.room_id,
             GameEvent::BettingTimerStarted {
                 betting_time_left_ms: msg.betting_time_left_ms,
                 round_id: msg.round_id,
                 server_seed_hash: msg.server_seed_hash,
-                next_round_server_seed_hash: msg.next_round_server_seed_hash,
+                seed_chain_hash: msg.seed_chain_hash,
             },
             None,
         );
@@ -267,6 +926,7 @@ impl Handler<BettingTimerUpdate> for GameServer {
     fn handle(&mut self, msg: BettingTimerUpdate, _: &mut Self::Context) -> Self::Result {
         // info!("time left: {:?}", msg.betting_time_left_ms);
         self.broadcast(
+            &msg.room_id,
             GameEvent::BettingTimerUpdate {
                 betting_time_left_ms: msg.betting_time_left_ms,
             },
@@ -275,12 +935,151 @@ impl Handler<BettingTimerUpdate> for GameServer {
     }
 }
 
+impl Handler<BettingTimerFinished> for GameServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: BettingTimerFinished, ctx: &mut Self::Context) -> Self::Result {
+        let Some(room) = self.rooms.get(&msg.room_id) else {
+            return;
+        };
+        // betting was cancelled, while the timer was finishing
+        if room.round_phase.phase() != RoundPhase::Betting {
+            return;
+        }
+        // players, who are still joining, don't count yet
+        let players_online = self
+            .peers
+            .values()
+            .filter(|peer| peer.ready && peer.room_id == msg.room_id)
+            .count() as u32;
+        let bets_placed = room.bet_map.len() as u32;
+
+        // bets of players, who left, are already released
+        if players_online == 0 {
+            info!("room {:?} is empty, round is not launched", msg.room_id);
+            self.change_phase(&msg.room_id, RoundPhase::Idle);
+            return;
+        }
+
+        let min_players = self.env_settings.min_round_players;
+        let min_bets = self.env_settings.min_round_bets;
+        if players_online < min_players || bets_placed < min_bets {
+            // game loop is paused, the round won't get more players
+            if self.game_stats.game_loop_state() != GameLoopState::Running {
+                self.cancel_betting(&msg.room_id);
+                return;
+            }
+            info!(
+                "waiting for players in room {:?}, players: {:?}, bets: {:?}",
+                msg.room_id, players_online, bets_placed
+            );
+            self.broadcast(
+                &msg.room_id,
+                GameEvent::WaitingForPlayers {
+                    players_online,
+                    bets_placed,
+                    min_players,
+                    min_bets,
+                    betting_time_left_ms: room.settings.betting_time_duration * 1000,
+                },
+                None,
+            );
+            room.crash_game.restart_betting_timer();
+            return;
+        }
+
+        // bets are closed from now on, they are taken from the balances before the round starts
+        if !self.change_phase(&msg.room_id, RoundPhase::Launching) {
+            return;
+        }
+        let room = &self.rooms[&msg.room_id];
+        let round_id = room.round_stats.round_id;
+        let uuids: Vec<String> = room.bet_map.keys().cloned().collect();
+        let room_id = msg.room_id;
+        self.spawn_balance_task(
+            ctx,
+            move |balance_system| {
+                uuids
+                    .into_iter()
+                    .filter(|uuid| {
+                        let tx_id = transaction_id(round_id, uuid, TransactionKind::Bet);
+                        match balance_system.commit_reserved_bet_amount(uuid, round_id, &tx_id) {
+                            Ok(_) => false,
+                            Err(err) => {
+                                warn!("unable to commit the bet of {:?}! {:?}", uuid, err);
+                                true
+                            }
+                        }
+                    })
+                    .collect::<Vec<String>>()
+            },
+            move |act, failed_bets| act.start_round(&room_id, failed_bets),
+        );
+    }
+}
+
 impl Handler<GameRoundUpdate> for GameServer {
     type Result = ();
 
-    fn handle(&mut self, msg: GameRoundUpdate, _: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: GameRoundUpdate, ctx: &mut Self::Context) -> Self::Result {
         // info!("multiplier: {:?}", msg.multiplier);
+        let Some(room) = self.rooms.get(&msg.room_id) else {
+            return;
+        };
+
+        // auto crash out the bets, whose target multiplier is reached, or whose win reached max win.
+        // Target equal to the crash point isn't reached, the round crashes at it.
+        let auto_crash_outs: Vec<(String, u32, CrashOutReason)> = room
+            .bet_map
+            .iter()
+            .filter_map(|(uuid, bet)| match bet.auto_crash_out_multiplier {
+                Some(target)
+                    if target < msg.multiplier || (target == msg.multiplier && !msg.crashed) =>
+                {
+                    Some((uuid.clone(), target, CrashOutReason::AutoCrashOut))
+                }
+                _ if bet.win_amount(msg.multiplier) >= bet.max_win => {
+                    Some((uuid.clone(), msg.multiplier, CrashOutReason::MaxWin))
+                }
+                _ => None,
+            })
+            .collect();
+        for (uuid, multiplier, reason) in auto_crash_outs {
+            self.crash_out(ctx, &msg.room_id, &uuid, multiplier, reason);
+        }
+
+        // remaining bets are settled, when their potential payout reaches the round liability cap.
+        // Bets still open at the crash point have lost.
+        let max_round_liability = self.env_settings.max_round_liability;
+        if let Some(room) = self.rooms.get(&msg.room_id) {
+            if max_round_liability > 0 && !msg.crashed {
+                let round_liability = room.bet_map.values().fold(0u64, |sum, bet| {
+                    sum.saturating_add(bet.win_amount(msg.multiplier))
+                });
+                if round_liability >= max_round_liability {
+                    let cap_multiplier = room.liability_cap_multiplier(max_round_liability);
+                    warn!(
+                        "round liability {:?} reached the cap at multiplier {:?}, crashing out {:?} bets",
+                        round_liability,
+                        cap_multiplier,
+                        room.bet_map.len()
+                    );
+                    let uuids: Vec<String> = room.bet_map.keys().cloned().collect();
+                    for uuid in uuids {
+                        self.crash_out(
+                            ctx,
+                            &msg.room_id,
+                            &uuid,
+                            cap_multiplier.min(msg.multiplier),
+                            CrashOutReason::MaxRoundLiability,
+                        );
+                    }
+                }
+            }
+        }
+
         self.broadcast(
+            &msg.room_id,
             GameEvent::GameRoundUpdate {
                 multiplier: msg.multiplier,
             },
@@ -292,34 +1091,547 @@ impl Handler<GameRoundUpdate> for GameServer {
 impl Handler<GameStarted> for GameServer {
     type Result = ();
 
-    fn handle(&mut self, _: GameStarted, _: &mut Self::Context) -> Self::Result {
-        // update balance system
-        for (uuid, _) in &self.bet_map {
-            // todo: handle error
-            self.balance_system.commit_reserved_bet_amount(uuid.as_str());
+    fn handle(&mut self, msg: GameStarted, _: &mut Self::Context) -> Self::Result {
+        if !self.change_phase(&msg.room_id, RoundPhase::Running) {
+            return;
         }
-        self.broadcast(GameEvent::GameStarted {}, None);
+        let Some(room) = self.rooms.get_mut(&msg.room_id) else {
+            return;
+        };
+
+        room.round_stats.started_at = room.round_phase.started_at(RoundPhase::Running);
+        room.round_stats.total_wagered = room.bet_map.values().map(|bet| bet.amount).sum();
+        room.round_stats.player_count = room.bet_map.len() as u32;
+        self.broadcast(&msg.room_id, GameEvent::GameStarted {}, None);
+    }
+}
+
+impl Handler<RoundResult> for GameServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: RoundResult, _: &mut Self::Context) -> Self::Result {
+        let Some(room) = self.rooms.get_mut(&msg.room_id) else {
+            return;
+        };
+        room.round_stats.crash_point = msg.crash_point;
+
+        let ended_at = Utc::now();
+        self.round_history.add(RoundRecord {
+            round_id: msg.round_id,
+            room_id: msg.room_id.clone(),
+            started_at: room.round_stats.started_at.unwrap_or(ended_at),
+            ended_at,
+            crash_point: msg.crash_point,
+            server_seed_hash: sha256(&msg.server_seed),
+            seed_chain_hash: room.round_stats.seed_chain_hash.clone(),
+            server_seed: msg.server_seed.clone(),
+            client_seed: msg.client_seed.clone(),
+            client_seed_inputs: msg.client_seed_inputs.clone(),
+            client_seed_salt: msg.client_seed_salt.clone(),
+            house_edge_pct: msg.house_edge_pct,
+            total_wagered: room.round_stats.total_wagered,
+            total_paid_out: room.round_stats.total_paid_out,
+            player_count: room.round_stats.player_count,
+        });
+
+        self.broadcast(
+            &msg.room_id,
+            GameEvent::RoundResult {
+                round_id: msg.round_id,
+                crash_point: msg.crash_point,
+                server_seed: msg.server_seed,
+                client_seed: msg.client_seed,
+                client_seed_inputs: msg.client_seed_inputs,
+                client_seed_salt: msg.client_seed_salt,
+                house_edge_pct: msg.house_edge_pct,
+            },
+            None,
+        );
+        self.change_phase(&msg.room_id, RoundPhase::Crashed);
     }
 }
 
 impl Handler<GameFinished> for GameServer {
     type Result = ();
 
-    fn handle(&mut self, _: GameFinished, _: &mut Self::Context) -> Self::Result {
-        self.broadcast(GameEvent::GameFinished {}, None);
-        self.bet_map.clear();
+    fn handle(&mut self, msg: GameFinished, ctx: &mut Self::Context) -> Self::Result {
+        self.broadcast(&msg.room_id, GameEvent::GameFinished {}, None);
 
-        if !self.peers.is_empty() {
-            self.crash_game.start_betting_timer();
+        // bets, that weren't crashed out, are lost
+        if !self.change_phase(&msg.room_id, RoundPhase::Settling) {
+            return;
         }
+        let Some(room) = self.rooms.get_mut(&msg.room_id) else {
+            return;
+        };
+        room.bet_map.clear();
+
+        let round_cooldown_ms = self.env_settings.round_cooldown_ms;
+        let mut winners = std::mem::take(&mut room.round_stats.winners);
+        winners.sort_by_key(|winner| Reverse(winner.win_amount));
+        winners.truncate(ROUND_SUMMARY_WINNERS_COUNT);
+        let round_summary = GameEvent::RoundSummary {
+            round_id: room.round_stats.round_id,
+            crash_point: room.round_stats.crash_point,
+            total_wagered: room.round_stats.total_wagered,
+            total_paid_out: room.round_stats.total_paid_out,
+            player_count: room.round_stats.player_count,
+            winners,
+            cooldown_ms: round_cooldown_ms as u32,
+        };
+        self.broadcast(&msg.room_id, round_summary, None);
+
+        // players see the result of the round, before betting for the next round opens
+        self.change_phase(&msg.room_id, RoundPhase::Cooldown);
+        let room_id = msg.room_id;
+        ctx.run_later(Duration::from_millis(round_cooldown_ms), move |act, _| {
+            act.change_phase(&room_id, RoundPhase::Idle);
+
+            if act.game_stats.game_loop_state() == GameLoopState::Running
+                && act.peers.values().any(|peer| peer.room_id == room_id)
+            {
+                act.start_betting(&room_id);
+            }
+        });
     }
 }
 
 impl Handler<GameError> for GameServer {
     type Result = ();
 
-    fn handle(&mut self, _: GameError, _: &mut Self::Context) -> Self::Result {
-        self.broadcast(GameEvent::GameError {}, None);
-        self.bet_map.clear();
+    fn handle(&mut self, msg: GameError, ctx: &mut Self::Context) -> Self::Result {
+        let Some(room) = self.rooms.get_mut(&msg.room_id) else {
+            return;
+        };
+        let round_id = room.round_stats.round_id;
+        // bets are committed before the round starts, so they are paid back
+        let bets: Vec<(String, u64)> = room
+            .bet_map
+            .drain()
+            .map(|(uuid, bet)| (uuid, bet.amount))
+            .collect();
+        self.spawn_balance_task(
+            ctx,
+            move |balance_system| {
+                for (uuid, amount) in bets {
+                    let tx_id = transaction_id(round_id, &uuid, TransactionKind::Refund);
+                    let bet_tx_id = transaction_id(round_id, &uuid, TransactionKind::Bet);
+                    if let Err(err) = balance_system
+                        .refund_bet_amount(&uuid, amount, round_id, &tx_id, &bet_tx_id)
+                    {
+                        warn!("unable to refund the bet of {:?}! {:?}", uuid, err);
+                    }
+                }
+            },
+            |_, _| {},
+        );
+
+        self.broadcast(&msg.room_id, GameEvent::GameError {}, None);
+    }
+}
+
+impl Handler<ChangeGameLoopState> for GameServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: ChangeGameLoopState, _: &mut Self::Context) -> Self::Result {
+        if self.game_stats.game_loop_state() == msg.state {
+            return;
+        }
+        info!("game loop state: {:?}", msg.state);
+        self.game_stats.set_game_loop_state(msg.state);
+
+        for peer in self.peers.values() {
+            peer.addr.do_send(GameEvent::GameLoopStateChanged {
+                state: msg.state.into(),
+            });
+        }
+
+        let room_ids: Vec<String> = self.rooms.keys().cloned().collect();
+        for room_id in room_ids {
+            let round_phase = self.rooms[&room_id].round_phase.phase();
+            match msg.state {
+                GameLoopState::Running => {
+                    if round_phase == RoundPhase::Idle
+                        && self.peers.values().any(|peer| peer.room_id == room_id)
+                    {
+                        self.start_betting(&room_id);
+                    }
+                }
+                // rounds in progress stop after their cooldown
+                GameLoopState::Paused => {}
+                // bets, that are not in a running round yet, are refunded
+                GameLoopState::Draining => {
+                    if round_phase == RoundPhase::Betting {
+                        self.cancel_betting(&room_id);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use actix::Addr;
+    use actix_web::rt::time;
+
+    use super::*;
+    use crate::services::{
+        balance_store::{BalanceStoreType, InMemoryBalanceStore},
+        currency::BetLimits,
+        ledger::Ledger,
+        room::parse_rooms,
+    };
+
+    /// Peer, that keeps the events it receives
+    struct TestPeer {
+        events: Arc<Mutex<Vec<GameEvent>>>,
+    }
+
+    impl Actor for TestPeer {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<GameEvent> for TestPeer {
+        type Result = ();
+
+        fn handle(&mut self, msg: GameEvent, _: &mut Self::Context) -> Self::Result {
+            self.events.lock().unwrap().push(msg);
+        }
+    }
+
+    struct TestServer {
+        addr: Addr<GameServer>,
+        room_registry: RoomRegistry,
+        balance_system: BalanceSystem,
+        game_stats: GameStats,
+    }
+
+    impl TestServer {
+        fn start(name: &str) -> Self {
+            Self::start_with(name, |_| {})
+        }
+
+        fn start_with(name: &str, configure: impl FnOnce(&mut EnvSettings)) -> Self {
+            let bet_limits = BetLimits::new(10, 10_000, 100_000).unwrap();
+            let mut env_settings = EnvSettings {
+                user_jwt_secret: String::new(),
+                user_jwt_expiration_minutes: 60,
+                server_port: 0,
+                betting_time_duration: 5,
+                house_edge_pct: 0.03,
+                multiplier_growth_rate: 0.00006,
+                game_tick_interval_ms: 50,
+                seed_chain_length: 100,
+                seed_chain_state_file: String::new(),
+                client_seed_salt: String::new(),
+                round_history_size: 10,
+                round_history_file: String::new(),
+                balance_store_type: BalanceStoreType::Memory,
+                balance_store_file: String::new(),
+                ledger_file: String::new(),
+                wallet_url: String::new(),
+                wallet_api_key: String::new(),
+                wallet_timeout_ms: 1_000,
+                fun_currency: "FUN".to_string(),
+                real_currency: "EUR".to_string(),
+                bet_limits,
+                currency_bet_limits: HashMap::new(),
+                max_round_liability: 0,
+                fun_refill_amount: 0,
+                fun_refill_cooldown_secs: 0,
+                round_cooldown_ms: 0,
+                min_round_players: 0,
+                min_round_bets: 0,
+                admin_api_key: String::new(),
+                rooms: parse_rooms(
+                    "fun:FUN:5:0.03:10:10000:100000,fast:FUN:5:0.03:10:10000:100000",
+                )
+                .unwrap(),
+            };
+            configure(&mut env_settings);
+            let seed_chain_state_file =
+                env::temp_dir().join(format!("crash-server-{}-{}.json", name, std::process::id()));
+            let _ = fs::remove_file(&seed_chain_state_file);
+            let seed_chain =
+                SeedChain::load_or_create(100, &seed_chain_state_file.to_string_lossy());
+            let room_registry = RoomRegistry::new(env_settings.rooms.clone());
+            let balance_system = BalanceSystem::new(
+                Arc::new(InMemoryBalanceStore::new()),
+                Ledger::in_memory(),
+                None,
+            );
+            let game_stats = GameStats::new();
+            let addr = GameServer::new(
+                game_stats.clone(),
+                env_settings,
+                balance_system.clone(),
+                seed_chain,
+                RoundHistory::new(10),
+                room_registry.clone(),
+            )
+            .start();
+
+            Self {
+                addr,
+                room_registry,
+                balance_system,
+                game_stats,
+            }
+        }
+
+        /// Joins the room with a new session, returns the events of the session
+        async fn join(
+            &self,
+            session_id: usize,
+            uuid: &str,
+            room_id: &str,
+        ) -> Arc<Mutex<Vec<GameEvent>>> {
+            let events = Arc::new(Mutex::new(Vec::new()));
+            let peer_addr = TestPeer {
+                events: events.clone(),
+            }
+            .start();
+            self.addr.do_send(PlayerJoined {
+                session_id,
+                uuid: uuid.to_string(),
+                client_seed: String::new(),
+                play_mode: PlayMode::FUN,
+                room_id: room_id.to_string(),
+                peer_addr: peer_addr.recipient(),
+            });
+            wait_for(&events, |event| {
+                matches!(event, GameEvent::PlayerJoinedResponse { .. })
+            })
+            .await;
+            events
+        }
+
+        async fn bet(
+            &self,
+            session_id: usize,
+            events: &Arc<Mutex<Vec<GameEvent>>>,
+            bet_amount: u64,
+        ) {
+            self.bet_with_target(session_id, events, bet_amount, None)
+                .await;
+        }
+
+        async fn bet_with_target(
+            &self,
+            session_id: usize,
+            events: &Arc<Mutex<Vec<GameEvent>>>,
+            bet_amount: u64,
+            auto_crash_out_multiplier: Option<u32>,
+        ) {
+            let peer_addr = TestPeer {
+                events: events.clone(),
+            }
+            .start();
+            self.addr.do_send(BetRequest {
+                session_id,
+                peer_addr: peer_addr.recipient(),
+                bet_amount,
+                auto_crash_out_multiplier,
+            });
+            wait_for(events, |event| {
+                matches!(
+                    event,
+                    GameEvent::BetResponse { .. } | GameEvent::BetError { .. }
+                )
+            })
+            .await;
+        }
+
+        /// Waits until the messages sent so far are handled
+        async fn sync(&self) {
+            self.addr.send(Connect {}).await.unwrap();
+        }
+
+        fn players_online(&self) -> Vec<u32> {
+            self.room_registry
+                .list()
+                .into_iter()
+                .map(|(_, players_online)| players_online)
+                .collect()
+        }
+    }
+
+    async fn wait_for(
+        events: &Arc<Mutex<Vec<GameEvent>>>,
+        is_expected: impl Fn(&GameEvent) -> bool,
+    ) {
+        for _ in 0..200 {
+            if events.lock().unwrap().iter().any(&is_expected) {
+                return;
+            }
+            time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("expected event is not received");
+    }
+
+    #[actix_web::test]
+    async fn test_disconnect_of_replaced_session_keeps_the_player() {
+        let server = TestServer::start("game-server-stale-disconnect");
+        server.join(1, "a", "fun").await;
+        let events = server.join(2, "a", "fast").await;
+        assert_eq!(server.players_online(), vec![0, 1]);
+
+        server.bet(2, &events, 100).await;
+        let balance = server.balance_system.fetch_balance("a");
+
+        // first tab is closed after the player joined from the second tab
+        server.addr.do_send(Disconnect { session_id: 1 });
+        server.sync().await;
+        assert_eq!(server.players_online(), vec![0, 1]);
+        assert_eq!(server.balance_system.fetch_balance("a"), balance);
+
+        server.addr.do_send(Disconnect { session_id: 2 });
+        server.sync().await;
+        assert_eq!(server.players_online(), vec![0, 0]);
+        assert_eq!(server.balance_system.fetch_balance("a"), balance + 100);
+    }
+
+    #[actix_web::test]
+    async fn test_players_online_counts_joined_players_once() {
+        let server = TestServer::start("game-server-players-online");
+        let players_online = || server.game_stats.players_online.load(Ordering::SeqCst);
+        server.join(1, "a", "fun").await;
+        server.join(2, "a", "fast").await;
+        assert_eq!(players_online(), 1);
+
+        // session, that failed to join or never joined, isn't counted
+        let events = Arc::new(Mutex::new(Vec::new()));
+        server.addr.do_send(PlayerJoined {
+            session_id: 3,
+            uuid: "b".to_string(),
+            client_seed: String::new(),
+            play_mode: PlayMode::FUN,
+            room_id: "unknown".to_string(),
+            peer_addr: TestPeer {
+                events: events.clone(),
+            }
+            .start()
+            .recipient(),
+        });
+        wait_for(&events, |event| {
+            matches!(event, GameEvent::JoinGameError { .. })
+        })
+        .await;
+        for session_id in [1, 3, 4] {
+            server.addr.do_send(Disconnect { session_id });
+        }
+        server.sync().await;
+        assert_eq!(players_online(), 1);
+
+        server.addr.do_send(Disconnect { session_id: 2 });
+        server.sync().await;
+        assert_eq!(players_online(), 0);
+    }
+
+    #[actix_web::test]
+    async fn test_rejoin_releases_the_bet_of_the_previous_room() {
+        let server = TestServer::start("game-server-rejoin");
+        let events = server.join(1, "a", "fun").await;
+        let balance = server.balance_system.fetch_balance("a");
+        server.bet(1, &events, 100).await;
+        assert_eq!(server.balance_system.fetch_balance("a"), balance - 100);
+
+        let events = server.join(2, "a", "fast").await;
+        assert_eq!(server.balance_system.fetch_balance("a"), balance);
+
+        // previous session can't bet for the player anymore
+        server.bet(2, &events, 200).await;
+        server.bet(1, &Arc::new(Mutex::new(Vec::new())), 300).await;
+        assert_eq!(server.balance_system.fetch_balance("a"), balance - 200);
+    }
+
+    #[actix_web::test]
+    async fn test_liability_cap_settles_bets_at_the_cap_multiplier() {
+        let server = TestServer::start_with("game-server-liability-cap", |env_settings| {
+            env_settings.max_round_liability = 1_000;
+        });
+        let events_a = server.join(1, "a", "fun").await;
+        let events_b = server.join(2, "b", "fun").await;
+        server.bet(1, &events_a, 100).await;
+        server.bet(2, &events_b, 100).await;
+
+        // crash point is above the cap, bets are paid where the cap was reached, not at the tick
+        server.addr.do_send(GameRoundUpdate {
+            room_id: "fun".to_string(),
+            multiplier: 520,
+            crashed: false,
+        });
+        for events in [&events_a, &events_b] {
+            wait_for(events, |event| {
+                matches!(
+                    event,
+                    GameEvent::CrashOutResponse { multiplier: 500, win_amount: 500, reason, .. }
+                        if *reason == u8::from(CrashOutReason::MaxRoundLiability)
+                )
+            })
+            .await;
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_auto_crash_out_at_the_exact_target() {
+        let server = TestServer::start("game-server-auto-crash-out");
+        let events_a = server.join(1, "a", "fun").await;
+        let events_b = server.join(2, "b", "fast").await;
+        server.bet_with_target(1, &events_a, 100, Some(200)).await;
+        server.bet_with_target(2, &events_b, 100, Some(200)).await;
+
+        server.addr.do_send(GameRoundUpdate {
+            room_id: "fun".to_string(),
+            multiplier: 200,
+            crashed: false,
+        });
+        wait_for(&events_a, |event| {
+            matches!(
+                event,
+                GameEvent::CrashOutResponse { multiplier: 200, win_amount: 200, reason, .. }
+                    if *reason == u8::from(CrashOutReason::AutoCrashOut)
+            )
+        })
+        .await;
+
+        // round crashed at the target
+        server.addr.do_send(GameRoundUpdate {
+            room_id: "fast".to_string(),
+            multiplier: 200,
+            crashed: true,
+        });
+        server.sync().await;
+        time::sleep(Duration::from_millis(100)).await;
+        assert!(!events_b
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|event| matches!(event, GameEvent::CrashOutResponse { .. })));
+    }
+
+    #[actix_web::test]
+    async fn test_bets_open_at_the_crash_are_lost() {
+        let server = TestServer::start_with("game-server-crash-liability", |env_settings| {
+            env_settings.max_round_liability = 1_000;
+        });
+        let events = server.join(1, "a", "fun").await;
+        server.bet(1, &events, 200).await;
+
+        server.addr.do_send(GameRoundUpdate {
+            room_id: "fun".to_string(),
+            multiplier: 600,
+            crashed: true,
+        });
+        server.sync().await;
+        time::sleep(Duration::from_millis(100)).await;
+        assert!(!events
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|event| matches!(event, GameEvent::CrashOutResponse { .. })));
     }
 }
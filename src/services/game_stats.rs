@@ -1,14 +1,59 @@
-use std::sync::{atomic::AtomicU32, Arc};
+use std::sync::{
+    atomic::{AtomicU32, AtomicU8, Ordering},
+    Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// State of the game loops of all rooms, changed by the admin for maintenance
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum GameLoopState {
+    /// rounds start one after another
+    Running,
+    /// rounds in progress are finished, next rounds don't start
+    Paused,
+    /// same as paused, bets placed in the betting phase are refunded right away
+    Draining,
+}
+
+impl From<GameLoopState> for u8 {
+    fn from(state: GameLoopState) -> u8 {
+        match state {
+            GameLoopState::Running => 0,
+            GameLoopState::Paused => 1,
+            GameLoopState::Draining => 2,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct GameStats {
     pub players_online: Arc<AtomicU32>,
+    /// see GameLoopState
+    game_loop_state: Arc<AtomicU8>,
+    /// rooms, whose round is not idle
+    pub active_rounds: Arc<AtomicU32>,
 }
 
 impl GameStats {
     pub fn new() -> Self {
         Self {
             players_online: Arc::new(AtomicU32::new(0)),
+            game_loop_state: Arc::new(AtomicU8::new(GameLoopState::Running.into())),
+            active_rounds: Arc::new(AtomicU32::new(0)),
+        }
+    }
+
+    pub fn game_loop_state(&self) -> GameLoopState {
+        match self.game_loop_state.load(Ordering::SeqCst) {
+            1 => GameLoopState::Paused,
+            2 => GameLoopState::Draining,
+            _ => GameLoopState::Running,
         }
     }
+
+    pub fn set_game_loop_state(&self, state: GameLoopState) {
+        self.game_loop_state.store(state.into(), Ordering::SeqCst);
+    }
 }
@@ -0,0 +1,366 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    sync::{
+        mpsc::{self, Sender},
+        Arc, RwLock,
+    },
+    thread,
+};
+
+use chrono::Utc;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{balance_store::StoredBalance, currency::default_currency};
+
+/// Accounts money moves between. Player money is either available, or reserved for a bet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LedgerAccount {
+    House,
+    PlayerAvailable,
+    PlayerReserved,
+    /// balance of a REAL mode player, held by the operator's wallet
+    Wallet,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LedgerEntryKind {
+    /// initial balance of a new player
+    OpeningBalance,
+    BetReserved,
+    BetCancelled,
+    BetCommitted,
+    Win,
+    /// committed bet paid back, when the round couldn't be completed
+    Refund,
+    /// FUN mode balance topped up by the player
+    Refill,
+}
+
+impl LedgerEntryKind {
+    /// (debit, credit) accounts of the entry, amount is moved from the credit to the debit account
+    pub fn accounts(&self) -> (LedgerAccount, LedgerAccount) {
+        match self {
+            LedgerEntryKind::OpeningBalance | LedgerEntryKind::Refill => {
+                (LedgerAccount::PlayerAvailable, LedgerAccount::House)
+            }
+            LedgerEntryKind::BetReserved => (
+                LedgerAccount::PlayerReserved,
+                LedgerAccount::PlayerAvailable,
+            ),
+            LedgerEntryKind::BetCancelled => (
+                LedgerAccount::PlayerAvailable,
+                LedgerAccount::PlayerReserved,
+            ),
+            LedgerEntryKind::BetCommitted => (LedgerAccount::House, LedgerAccount::PlayerReserved),
+            LedgerEntryKind::Win | LedgerEntryKind::Refund => {
+                (LedgerAccount::PlayerAvailable, LedgerAccount::House)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerEntry {
+    pub tx_id: String,
+    pub round_id: Option<u32>,
+    pub uuid: String,
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    pub kind: LedgerEntryKind,
+    pub debit_account: LedgerAccount,
+    pub credit_account: LedgerAccount,
+    pub amount: u64,
+    /// available balance of the player after the entry,
+    /// balance reported by the wallet for REAL mode players
+    pub balance_after: u64,
+    /// unix timestamp in milliseconds
+    pub created_at: i64,
+}
+
+/// newest entries of every player kept in memory
+pub const MAX_PLAYER_ENTRIES: usize = 200;
+
+/// newest entries of all players kept in memory, used to recognize transactions
+/// completed before a restart
+pub const MAX_RECENT_ENTRIES: usize = 100_000;
+
+#[derive(Debug, Default)]
+struct LedgerState {
+    /// newest entries of every player, newest last
+    player_entries: HashMap<String, VecDeque<LedgerEntry>>,
+    /// newest entries of all players, newest last
+    recent_entries: VecDeque<LedgerEntry>,
+    /// (available, reserved) of every player and currency, the sum of all entries
+    balances: HashMap<(String, String), (i128, i128)>,
+}
+
+impl LedgerState {
+    fn push(&mut self, entry: LedgerEntry) {
+        // balances held by the wallet aren't replayed
+        if entry.debit_account != LedgerAccount::Wallet
+            && entry.credit_account != LedgerAccount::Wallet
+        {
+            let (available, reserved) = self
+                .balances
+                .entry((entry.uuid.clone(), entry.currency.clone()))
+                .or_default();
+            for (account, amount) in [
+                (entry.debit_account, entry.amount as i128),
+                (entry.credit_account, -(entry.amount as i128)),
+            ] {
+                match account {
+                    LedgerAccount::House | LedgerAccount::Wallet => {}
+                    LedgerAccount::PlayerAvailable => *available += amount,
+                    LedgerAccount::PlayerReserved => *reserved += amount,
+                }
+            }
+        }
+
+        let player_entries = self.player_entries.entry(entry.uuid.clone()).or_default();
+        if player_entries.len() >= MAX_PLAYER_ENTRIES {
+            player_entries.pop_front();
+        }
+        player_entries.push_back(entry.clone());
+
+        if self.recent_entries.len() >= MAX_RECENT_ENTRIES {
+            self.recent_entries.pop_front();
+        }
+        self.recent_entries.push_back(entry);
+    }
+}
+
+/// Immutable record of every balance movement. Entries are only ever appended.
+///
+/// Only the newest entries are kept in memory, together with the balances the entries add up to.
+/// Entries are written to the file by a background thread, so writing never blocks the caller.
+#[derive(Debug, Clone)]
+pub struct Ledger {
+    state: Arc<RwLock<LedgerState>>,
+    writer: Option<Sender<LedgerEntry>>,
+}
+
+impl Ledger {
+    pub fn in_memory() -> Self {
+        Self {
+            state: Arc::new(RwLock::new(LedgerState::default())),
+            writer: None,
+        }
+    }
+
+    /// Ledger persisted to a JSON lines file, existing entries are read from the file.
+    pub fn open(file_path: &str) -> Self {
+        let mut state = LedgerState::default();
+        if let Ok(file) = File::open(file_path) {
+            let mut entry_count = 0;
+            for line in BufReader::new(file).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(err) => {
+                        warn!("unable to read ledger file! {:?}", err);
+                        break;
+                    }
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<LedgerEntry>(&line) {
+                    Ok(entry) => {
+                        state.push(entry);
+                        entry_count += 1;
+                    }
+                    Err(err) => warn!("skipping invalid ledger entry! {:?}", err),
+                }
+            }
+            info!(
+                "loaded {:?} ledger entries from {:?}",
+                entry_count, file_path
+            );
+        }
+
+        if let Some(dir) = Path::new(file_path).parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(file_path)
+            .expect("ledger file must be writable");
+
+        let (writer, receiver) = mpsc::channel::<LedgerEntry>();
+        thread::spawn(move || {
+            for entry in receiver {
+                let line = serde_json::to_string(&entry).expect("ledger entry is serializable");
+                if let Err(err) = writeln!(file, "{}", line) {
+                    warn!("unable to write ledger entry! {:?} {:?}", entry, err);
+                }
+            }
+        });
+
+        Self {
+            state: Arc::new(RwLock::new(state)),
+            writer: Some(writer),
+        }
+    }
+
+    /// Appends an entry. Entries without a transaction id get a random one.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(
+        &self,
+        tx_id: Option<&str>,
+        uuid: &str,
+        currency: &str,
+        round_id: Option<u32>,
+        kind: LedgerEntryKind,
+        amount: u64,
+        balance_after: u64,
+    ) {
+        let (debit_account, credit_account) = kind.accounts();
+        self.append(LedgerEntry {
+            tx_id: tx_id.map_or_else(|| Uuid::new_v4().to_string(), str::to_string),
+            round_id,
+            uuid: uuid.to_string(),
+            currency: currency.to_string(),
+            kind,
+            debit_account,
+            credit_account,
+            amount,
+            balance_after,
+            created_at: Utc::now().timestamp_millis(),
+        });
+    }
+
+    /// Appends an entry of a REAL mode player, whose balance is held by the wallet.
+    /// `balance_after` is the balance reported by the wallet.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_wallet(
+        &self,
+        tx_id: &str,
+        uuid: &str,
+        currency: &str,
+        round_id: u32,
+        kind: LedgerEntryKind,
+        amount: u64,
+        balance_after: u64,
+    ) {
+        let to_wallet = |account| match account {
+            LedgerAccount::House => LedgerAccount::House,
+            _ => LedgerAccount::Wallet,
+        };
+        let (debit_account, credit_account) = kind.accounts();
+        self.append(LedgerEntry {
+            tx_id: tx_id.to_string(),
+            round_id: Some(round_id),
+            uuid: uuid.to_string(),
+            currency: currency.to_string(),
+            kind,
+            debit_account: to_wallet(debit_account),
+            credit_account: to_wallet(credit_account),
+            amount,
+            balance_after,
+            created_at: Utc::now().timestamp_millis(),
+        });
+    }
+
+    fn append(&self, entry: LedgerEntry) {
+        if let Some(writer) = &self.writer {
+            if let Err(err) = writer.send(entry.clone()) {
+                warn!("unable to write ledger entry! {:?}", err.0);
+            }
+        }
+        self.state.write().unwrap().push(entry);
+    }
+
+    /// Entries of the player, newest first. Only the newest `MAX_PLAYER_ENTRIES` are kept.
+    pub fn entries_of(&self, uuid: &str, limit: usize) -> Vec<LedgerEntry> {
+        let state = self.state.read().unwrap();
+        state
+            .player_entries
+            .get(uuid)
+            .map_or(Vec::new(), |entries| {
+                entries.iter().rev().take(limit).cloned().collect()
+            })
+    }
+
+    /// Last entries of all players, newest first. Only the newest `MAX_RECENT_ENTRIES` are kept.
+    pub fn recent_entries(&self, limit: usize) -> Vec<LedgerEntry> {
+        let state = self.state.read().unwrap();
+        state
+            .recent_entries
+            .iter()
+            .rev()
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// Replays all entries, returns the balance and the reserved amount of every player
+    /// in every currency, keyed by (uuid, currency). Balances held by the wallet are skipped.
+    pub fn replay(&self) -> HashMap<(String, String), StoredBalance> {
+        let state = self.state.read().unwrap();
+        state
+            .balances
+            .iter()
+            .map(|(key, (available, reserved))| {
+                // balance of a player includes the reserved amount, until the bet is committed
+                let balance = StoredBalance {
+                    balance: (available + reserved).max(0) as u64,
+                    reserved: (*reserved).max(0) as u64,
+                };
+                (key.clone(), balance)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_newest_entries_are_kept() {
+        let ledger = Ledger::in_memory();
+        ledger.record(
+            None,
+            "a",
+            "FUN",
+            None,
+            LedgerEntryKind::OpeningBalance,
+            1_000,
+            1_000,
+        );
+        for round_id in 0..MAX_PLAYER_ENTRIES as u32 {
+            ledger.record(
+                None,
+                "a",
+                "FUN",
+                Some(round_id),
+                LedgerEntryKind::Win,
+                10,
+                1_010 + round_id as u64 * 10,
+            );
+        }
+
+        let entries = ledger.entries_of("a", MAX_PLAYER_ENTRIES + 1);
+        assert_eq!(entries.len(), MAX_PLAYER_ENTRIES);
+        assert_eq!(entries[0].round_id, Some(MAX_PLAYER_ENTRIES as u32 - 1));
+        assert!(ledger.entries_of("b", 10).is_empty());
+
+        // balances still add up all entries
+        let balances = ledger.replay();
+        assert_eq!(
+            balances[&("a".to_string(), "FUN".to_string())],
+            StoredBalance {
+                balance: 1_000 + MAX_PLAYER_ENTRIES as u64 * 10,
+                reserved: 0,
+            }
+        );
+    }
+}
@@ -1,5 +1,12 @@
+use std::time::Instant;
+
 use actix::{Message, Recipient};
 
+use crate::{
+    routes::auth::PlayMode,
+    services::{currency::BetLimits, game_stats::GameLoopState},
+};
+
 // messages sent between peer and gameServer
 
 #[derive(Message)]
@@ -18,6 +25,11 @@ pub struct Disconnect {
 pub struct PlayerJoined {
     pub session_id: usize,
     pub uuid: String,
+    /// seed contributed by the player, empty if none
+    pub client_seed: String,
+    pub play_mode: PlayMode,
+    /// empty, if the player joins the default room of the currency
+    pub room_id: String,
     pub peer_addr: Recipient<GameEvent>,
 }
 
@@ -25,13 +37,111 @@ pub struct PlayerJoined {
 #[rtype(result = "()")]
 pub struct BetRequest {
     pub session_id: usize,
+    pub peer_addr: Recipient<GameEvent>,
     pub bet_amount: u64,
+    pub auto_crash_out_multiplier: Option<u32>,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetClientSeed {
+    pub session_id: usize,
+    pub client_seed: String,
 }
 
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct CrashOutRequest {
     pub session_id: usize,
+    pub peer_addr: Recipient<GameEvent>,
+    /// time the request was received from the client
+    pub requested_at: Instant,
+}
+
+/// Player asks to top up the FUN mode balance
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RefillBalanceRequest {
+    pub session_id: usize,
+    pub peer_addr: Recipient<GameEvent>,
+}
+
+/// Admin pauses, resumes or drains the game loops of all rooms
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ChangeGameLoopState {
+    pub state: GameLoopState,
+}
+
+/// Error codes sent to the peer in JoinGameError, BetError, CrashOutError and RefillBalanceError
+#[derive(Debug, Clone, Copy)]
+pub enum ErrorCode {
+    NotJoined,
+    InsufficientBalance,
+    BettingClosed,
+    BetBelowMin,
+    BetAboveMax,
+    NoActiveBet,
+    RoundAlreadyCrashed,
+    WalletUnavailable,
+    RefillNotAllowed,
+    RefillCooldown,
+    UnknownRoom,
+    RoomCurrencyNotAccepted,
+    ActiveBetInAnotherRoom,
+}
+
+impl From<ErrorCode> for u8 {
+    fn from(code: ErrorCode) -> u8 {
+        match code {
+            ErrorCode::NotJoined => 1,
+            ErrorCode::InsufficientBalance => 2,
+            ErrorCode::BettingClosed => 3,
+            ErrorCode::BetBelowMin => 4,
+            ErrorCode::BetAboveMax => 5,
+            ErrorCode::NoActiveBet => 6,
+            ErrorCode::RoundAlreadyCrashed => 7,
+            ErrorCode::WalletUnavailable => 8,
+            ErrorCode::RefillNotAllowed => 9,
+            ErrorCode::RefillCooldown => 10,
+            ErrorCode::UnknownRoom => 11,
+            ErrorCode::RoomCurrencyNotAccepted => 12,
+            ErrorCode::ActiveBetInAnotherRoom => 13,
+        }
+    }
+}
+
+/// Why a bet was crashed out, sent to the peer in CrashOutResponse
+#[derive(Debug, Clone, Copy)]
+pub enum CrashOutReason {
+    /// crash out requested by the player
+    Manual,
+    /// auto crash out multiplier of the bet is reached
+    AutoCrashOut,
+    /// win of the bet reached the max win
+    MaxWin,
+    /// potential payout of all bets in the round reached the max round liability
+    MaxRoundLiability,
+}
+
+impl From<CrashOutReason> for u8 {
+    fn from(reason: CrashOutReason) -> u8 {
+        match reason {
+            CrashOutReason::Manual => 0,
+            CrashOutReason::AutoCrashOut => 1,
+            CrashOutReason::MaxWin => 2,
+            CrashOutReason::MaxRoundLiability => 3,
+        }
+    }
+}
+
+/// Bet of the round, that was crashed out before the crash
+#[derive(Debug, Clone)]
+pub struct RoundWinner {
+    pub display_name: String,
+    pub bet_amount: u64,
+    pub win_amount: u64,
+    pub multiplier: u32,
 }
 
 #[derive(Message, Debug, Clone)]
@@ -60,23 +170,42 @@ pub enum GameEvent {
         betting_time_left_ms: u32,
         /// in milliseconds
         round_time_elapsed_ms: u32,
+        multiplier_growth_rate: f64,
         display_name: String,
         balance: u64,
+        /// newest first
+        recent_crash_points: Vec<u32>,
+        currency: String,
+        bet_limits: BetLimits,
+        room_id: String,
+    },
+    JoinGameError {
+        code: u8,
     },
     BetResponse {
         balance: u64,
+        currency: String,
+    },
+    BetError {
+        code: u8,
     },
     CrashOutResponse {
         win_amount: u64,
         multiplier: u32,
         balance: u64,
+        /// see CrashOutReason
+        reason: u8,
+    },
+    CrashOutError {
+        code: u8,
     },
     BettingTimerStarted {
         /// in milliseconds
         betting_time_left_ms: u32,
         round_id: u32,
         server_seed_hash: String,
-        next_round_server_seed_hash: String,
+        /// hash the server seed chain terminates in
+        seed_chain_hash: String,
     },
     BettingTimerUpdate {
         /// in milliseconds
@@ -88,41 +217,118 @@ pub enum GameEvent {
     },
     GameFinished {},
     GameError {},
+    RoundResult {
+        round_id: u32,
+        crash_point: u32,
+        server_seed: String,
+        client_seed: String,
+        client_seed_inputs: Vec<String>,
+        client_seed_salt: String,
+        house_edge_pct: f32,
+    },
+    RefillBalanceResponse {
+        balance: u64,
+    },
+    RoundPhaseChanged {
+        /// see RoundPhase
+        phase: u8,
+        /// final multiplier of the round, 0 until the round crashed
+        crash_point: u32,
+    },
+    RoundSummary {
+        round_id: u32,
+        crash_point: u32,
+        total_wagered: u64,
+        total_paid_out: u64,
+        player_count: u32,
+        /// biggest wins first
+        winners: Vec<RoundWinner>,
+        /// time until betting for the next round opens
+        cooldown_ms: u32,
+    },
+    WaitingForPlayers {
+        players_online: u32,
+        bets_placed: u32,
+        min_players: u32,
+        min_bets: u32,
+        betting_time_left_ms: u32,
+    },
+    GameLoopStateChanged {
+        /// see GameLoopState
+        state: u8,
+    },
+    RefillBalanceError {
+        code: u8,
+    },
 }
 
-// messages between gameServer and CrashGame
+// messages between gameServer and CrashGame, room_id is the room of the CrashGame
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct BettingTimerStarted {
+    pub room_id: String,
     /// in milliseconds
     pub betting_time_left_ms: u32,
     pub round_id: u32,
     pub server_seed_hash: String,
-    pub next_round_server_seed_hash: String,
+    /// hash the server seed chain terminates in
+    pub seed_chain_hash: String,
 }
 
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct BettingTimerUpdate {
+    pub room_id: String,
     /// in milliseconds
     pub betting_time_left_ms: u32,
 }
 
+/// Betting time is over, bets are closed
 #[derive(Message)]
 #[rtype(result = "()")]
-pub struct GameStarted {}
+pub struct BettingTimerFinished {
+    pub room_id: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct GameStarted {
+    pub room_id: String,
+}
 
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct GameRoundUpdate {
+    pub room_id: String,
     /// in milliseconds
     pub multiplier: u32,
+    /// true for the last update, sent at the crash point, bets still open have lost
+    pub crashed: bool,
 }
 
 #[derive(Message)]
 #[rtype(result = "()")]
-pub struct GameFinished {}
+pub struct GameFinished {
+    pub room_id: String,
+}
 
 #[derive(Message)]
 #[rtype(result = "()")]
-pub struct GameError {}
+pub struct RoundResult {
+    pub room_id: String,
+    pub round_id: u32,
+    /// final multiplier
+    pub crash_point: u32,
+    pub server_seed: String,
+    pub client_seed: String,
+    /// seeds of the players, the client seed is derived from
+    pub client_seed_inputs: Vec<String>,
+    pub client_seed_salt: String,
+    pub house_edge_pct: f32,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct GameError {
+    pub room_id: String,
+}
@@ -0,0 +1,143 @@
+//! Local "seamless wallet" server, used to test the wallet integration.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use serde_json::{json, Value};
+use tiny_http::{Header, Response, Server};
+
+#[derive(Debug, Default)]
+struct MockWalletState {
+    /// session token -> player id
+    tokens: HashMap<String, String>,
+    balances: HashMap<String, u64>,
+    /// transaction id -> (amount, is_debit)
+    transactions: HashMap<String, (u64, bool)>,
+    /// number of following requests, that are handled without sending the response back
+    lost_responses: usize,
+}
+
+pub struct MockWalletServer {
+    server: Arc<Server>,
+    state: Arc<Mutex<MockWalletState>>,
+}
+
+impl MockWalletServer {
+    pub fn start() -> Self {
+        let server = Arc::new(Server::http("127.0.0.1:0").expect("mock wallet can listen"));
+        let state = Arc::new(Mutex::new(MockWalletState::default()));
+
+        let thread_server = server.clone();
+        let thread_state = state.clone();
+        thread::spawn(move || {
+            for mut request in thread_server.incoming_requests() {
+                let mut body = String::new();
+                let _ = request.as_reader().read_to_string(&mut body);
+                let data: Value = serde_json::from_str(&body).unwrap_or(Value::Null);
+
+                let (result, is_lost) = {
+                    let mut state = thread_state.lock().unwrap();
+                    let result = state.handle(request.url(), &data);
+                    let is_lost = state.lost_responses > 0;
+                    state.lost_responses = state.lost_responses.saturating_sub(1);
+                    (result, is_lost)
+                };
+                let (status, response_data) = match result {
+                    // client sees the same as on a timeout, the request was applied anyway
+                    _ if is_lost => (503, json!({ "error": "UNAVAILABLE" })),
+                    Ok(data) => (200, data),
+                    Err(error) => (400, json!({ "error": error })),
+                };
+
+                let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+                let response = Response::from_string(response_data.to_string())
+                    .with_status_code(status)
+                    .with_header(content_type);
+                let _ = request.respond(response);
+            }
+        });
+
+        Self { server, state }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.server.server_addr())
+    }
+
+    pub fn add_player(&self, token: &str, player_id: &str, balance: u64) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .tokens
+            .insert(token.to_string(), player_id.to_string());
+        state.balances.insert(player_id.to_string(), balance);
+    }
+
+    pub fn balance(&self, player_id: &str) -> Option<u64> {
+        self.state.lock().unwrap().balances.get(player_id).copied()
+    }
+
+    /// Next `count` requests are applied, but answered as if the wallet was unavailable
+    pub fn lose_next_responses(&self, count: usize) {
+        self.state.lock().unwrap().lost_responses = count;
+    }
+}
+
+impl Drop for MockWalletServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+    }
+}
+
+impl MockWalletState {
+    fn handle(&mut self, path: &str, data: &Value) -> Result<Value, &'static str> {
+        if path == "/authenticate" {
+            let token = data["token"].as_str().unwrap_or_default();
+            let player_id = self.tokens.get(token).ok_or("INVALID_TOKEN")?.clone();
+            let balance = self.balances[&player_id];
+            return Ok(json!({ "playerId": player_id, "balance": balance }));
+        }
+
+        let player_id = data["playerId"].as_str().unwrap_or_default().to_string();
+        let balance = *self.balances.get(&player_id).ok_or("UNKNOWN_PLAYER")?;
+        let tx_id = data["transactionId"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        let amount = data["amount"].as_u64().unwrap_or_default();
+
+        // repeated transaction, it is only applied once
+        if path != "/balance" && self.transactions.contains_key(&tx_id) {
+            return Ok(json!({ "balance": balance }));
+        }
+
+        let new_balance = match path {
+            "/balance" => balance,
+            "/debit" => {
+                if balance < amount {
+                    return Err("INSUFFICIENT_FUNDS");
+                }
+                self.transactions.insert(tx_id, (amount, true));
+                balance - amount
+            }
+            "/credit" => {
+                self.transactions.insert(tx_id, (amount, false));
+                balance + amount
+            }
+            "/rollback" => {
+                let debit_tx_id = data["referenceTransactionId"].as_str().unwrap_or_default();
+                let refund_amount = match self.transactions.get(debit_tx_id) {
+                    Some((amount, true)) => *amount,
+                    _ => 0,
+                };
+                self.transactions.insert(tx_id, (refund_amount, false));
+                balance + refund_amount
+            }
+            _ => return Err("UNKNOWN_OPERATION"),
+        };
+        self.balances.insert(player_id, new_balance);
+        Ok(json!({ "balance": new_balance }))
+    }
+}
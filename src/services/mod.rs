@@ -1,9 +1,19 @@
+pub mod balance_store;
 pub mod balance_system;
 pub mod crash_game;
 pub mod crash_game_math;
+pub mod currency;
 pub mod env_settings;
 pub mod game_server;
 pub mod game_stats;
 pub mod generate_username;
+pub mod ledger;
 pub mod message_types;
+#[cfg(test)]
+mod mock_wallet_server;
 pub mod peer;
+pub mod room;
+pub mod round_history;
+pub mod round_phase;
+pub mod seed_chain;
+pub mod wallet_provider;
@@ -10,9 +10,11 @@ use log::info;
 
 use crate::{
     routes::utils::auth_token_extractor::UserAuthentication,
-    services::message_types::{BetRequest, CrashOutRequest, PlayerJoined},
+    services::message_types::{
+        BetRequest, CrashOutRequest, PlayerJoined, RefillBalanceRequest, SetClientSeed,
+    },
     utils::flatbuffer_utils::{
-        create_bet_response, create_betting_timer_started_response, create_betting_timer_update_response, create_crash_out_response, create_game_finished_response, create_game_started_response, create_game_update_response, create_join_game_response_success, create_remote_player_bets_placed_response, create_remote_player_crash_out_response, create_remote_player_joined_response, create_remote_player_left_response, parse_gameplay_data
+        create_bet_error_response, create_bet_response, create_betting_timer_started_response, create_betting_timer_update_response, create_crash_out_error_response, create_crash_out_response, create_game_finished_response, create_game_loop_state_changed_response, create_game_started_response, create_game_update_response, create_join_game_error_response, create_join_game_response_success, create_refill_balance_error_response, create_refill_balance_response, create_remote_player_bets_placed_response, create_remote_player_crash_out_response, create_remote_player_joined_response, create_remote_player_left_response, create_round_phase_changed_response, create_round_result_response, create_round_summary_response, create_waiting_for_players_response, parse_gameplay_data
     },
 };
 
@@ -27,12 +29,21 @@ pub enum ClientData {
     JoinGameRequest {
         player_uuid: String,
         jwt_token: String,
+        client_seed: String,
+        /// empty, if the player didn't ask for a room
+        room_id: String,
+    },
+    SetClientSeedRequest {
+        client_seed: String,
     },
     BetRequest {
         /// in cents
         bet_amount: u64,
+        /// multiplier (x100) at which the bet is crashed out automatically
+        auto_crash_out_multiplier: Option<u32>,
     },
     CrashOutRequest {},
+    RefillBalanceRequest {},
     Unknown,
 }
 
@@ -103,30 +114,44 @@ impl Handler<GameEvent> for Peer {
                 betting_time_left_ms,
                 multiplier,
                 round_time_elapsed_ms,
+                multiplier_growth_rate,
                 display_name,
                 balance,
+                recent_crash_points,
+                currency,
+                bet_limits,
+                room_id,
             } => {
                 let response_data = create_join_game_response_success(
                     game_state,
                     betting_time_left_ms,
                     multiplier,
                     round_time_elapsed_ms,
+                    multiplier_growth_rate,
                     display_name,
                     balance,
+                    recent_crash_points,
+                    currency,
+                    bet_limits,
+                    room_id,
                 );
                 ctx.binary(response_data);
             }
+            GameEvent::JoinGameError { code } => {
+                let response_data = create_join_game_error_response(code);
+                ctx.binary(response_data);
+            }
             GameEvent::BettingTimerStarted {
                 betting_time_left_ms,
                 round_id,
                 server_seed_hash,
-                next_round_server_seed_hash,
+                seed_chain_hash,
             } => {
                 let response_data = create_betting_timer_started_response(
                     betting_time_left_ms,
                     round_id,
                     server_seed_hash,
-                    next_round_server_seed_hash,
+                    seed_chain_hash,
                 );
                 ctx.binary(response_data);
             }
@@ -148,20 +173,50 @@ impl Handler<GameEvent> for Peer {
                 // let response_data = create_game_finished_response();
                 // ctx.binary(response_data);
             }
+            GameEvent::RoundResult {
+                round_id,
+                crash_point,
+                server_seed,
+                client_seed,
+                client_seed_inputs,
+                client_seed_salt,
+                house_edge_pct,
+            } => {
+                let response_data = create_round_result_response(
+                    round_id,
+                    crash_point,
+                    server_seed,
+                    client_seed,
+                    client_seed_inputs,
+                    client_seed_salt,
+                    house_edge_pct,
+                );
+                ctx.binary(response_data);
+            }
             GameEvent::GameRoundUpdate { multiplier } => {
                 let response_data = create_game_update_response(multiplier);
                 ctx.binary(response_data);
             }
-            GameEvent::BetResponse { balance } => {
-                let response_data = create_bet_response(balance);
+            GameEvent::BetResponse { balance, currency } => {
+                let response_data = create_bet_response(balance, currency);
+                ctx.binary(response_data);
+            }
+            GameEvent::BetError { code } => {
+                let response_data = create_bet_error_response(code);
                 ctx.binary(response_data);
             }
             GameEvent::CrashOutResponse {
                 win_amount,
                 multiplier,
                 balance,
+                reason,
             } => {
-                let response_data = create_crash_out_response(win_amount, multiplier, balance);
+                let response_data =
+                    create_crash_out_response(win_amount, multiplier, balance, reason);
+                ctx.binary(response_data);
+            }
+            GameEvent::CrashOutError { code } => {
+                let response_data = create_crash_out_error_response(code);
                 ctx.binary(response_data);
             }
             GameEvent::RemotePlayerJoined {
@@ -196,6 +251,58 @@ impl Handler<GameEvent> for Peer {
                     create_remote_player_crash_out_response(display_name, win_amount);
                 ctx.binary(response_data);
             }
+            GameEvent::RefillBalanceResponse { balance } => {
+                let response_data = create_refill_balance_response(balance);
+                ctx.binary(response_data);
+            }
+            GameEvent::RefillBalanceError { code } => {
+                let response_data = create_refill_balance_error_response(code);
+                ctx.binary(response_data);
+            }
+            GameEvent::RoundPhaseChanged { phase, crash_point } => {
+                let response_data = create_round_phase_changed_response(phase, crash_point);
+                ctx.binary(response_data);
+            }
+            GameEvent::RoundSummary {
+                round_id,
+                crash_point,
+                total_wagered,
+                total_paid_out,
+                player_count,
+                winners,
+                cooldown_ms,
+            } => {
+                let response_data = create_round_summary_response(
+                    round_id,
+                    crash_point,
+                    total_wagered,
+                    total_paid_out,
+                    player_count,
+                    winners,
+                    cooldown_ms,
+                );
+                ctx.binary(response_data);
+            }
+            GameEvent::WaitingForPlayers {
+                players_online,
+                bets_placed,
+                min_players,
+                min_bets,
+                betting_time_left_ms,
+            } => {
+                let response_data = create_waiting_for_players_response(
+                    players_online,
+                    bets_placed,
+                    min_players,
+                    min_bets,
+                    betting_time_left_ms,
+                );
+                ctx.binary(response_data);
+            }
+            GameEvent::GameLoopStateChanged { state } => {
+                let response_data = create_game_loop_state_changed_response(state);
+                ctx.binary(response_data);
+            }
         }
     }
 }
@@ -222,6 +329,8 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for Peer {
                     ClientData::JoinGameRequest {
                         jwt_token,
                         player_uuid,
+                        client_seed,
+                        room_id,
                     } => {
                         // todo: check for already logged in
                         match UserAuthentication::validate_auth(
@@ -229,11 +338,14 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for Peer {
                             &jwt_token,
                             &self.env_settings,
                         ) {
-                            Ok(_) => {
+                            Ok(user_auth) => {
                                 let peer_addr = ctx.address();
                                 self.game_server_addr.do_send(PlayerJoined {
                                     session_id: self.session_id,
                                     uuid: player_uuid.clone(),
+                                    client_seed,
+                                    play_mode: user_auth.play_mode,
+                                    room_id,
                                     peer_addr: peer_addr.recipient(),
                                 });
                             }
@@ -248,17 +360,36 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for Peer {
                             }
                         };
                     }
-                    ClientData::BetRequest { bet_amount } => {
+                    ClientData::BetRequest {
+                        bet_amount,
+                        auto_crash_out_multiplier,
+                    } => {
                         // info!("bet request {:?} {:?}", bet_amount, self.session_id);
                         self.game_server_addr.do_send(BetRequest {
                             session_id: self.session_id,
+                            peer_addr: ctx.address().recipient(),
                             bet_amount: bet_amount,
+                            auto_crash_out_multiplier,
+                        });
+                    }
+                    ClientData::SetClientSeedRequest { client_seed } => {
+                        self.game_server_addr.do_send(SetClientSeed {
+                            session_id: self.session_id,
+                            client_seed,
                         });
                     }
                     ClientData::CrashOutRequest {} => {
                         info!("crash out {:?}", self.session_id);
                         self.game_server_addr.do_send(CrashOutRequest {
                             session_id: self.session_id,
+                            peer_addr: ctx.address().recipient(),
+                            requested_at: Instant::now(),
+                        });
+                    }
+                    ClientData::RefillBalanceRequest {} => {
+                        self.game_server_addr.do_send(RefillBalanceRequest {
+                            session_id: self.session_id,
+                            peer_addr: ctx.address().recipient(),
                         });
                     }
                     ClientData::Unknown => {}
@@ -0,0 +1,201 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use super::currency::BetLimits;
+
+/// Table with its own game loop, created from the config
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoomSettings {
+    pub id: String,
+    /// currency of the bets, only players with a balance in this currency can join
+    pub currency: String,
+    /// in seconds
+    pub betting_time_duration: u32,
+    /// value between 0 to 1
+    pub house_edge_pct: f32,
+    pub bet_limits: BetLimits,
+}
+
+/// Parses rooms, e.g. `fun:FUN:5:0.03:100:100000000:1000000000,eur:EUR:10:0.01:10:1000000:50000000`
+pub fn parse_rooms(value: &str) -> Result<Vec<RoomSettings>, String> {
+    let mut rooms = Vec::new();
+    let mut room_ids = HashSet::new();
+    for item in value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+    {
+        let parts: Vec<&str> = item.split(':').map(str::trim).collect();
+        let [id, currency, betting_time_duration, house_edge_pct, min_bet, max_bet, max_win] =
+            parts[..]
+        else {
+            return Err(format!(
+                "{:?} must be id:currency:betting_time_duration:house_edge:min_bet:max_bet:max_win",
+                item
+            ));
+        };
+        if id.is_empty() || currency.is_empty() {
+            return Err(format!("missing room id or currency in {:?}", item));
+        }
+        if !room_ids.insert(id) {
+            return Err(format!("duplicate room id {:?}", id));
+        }
+        let betting_time_duration = betting_time_duration
+            .parse::<u32>()
+            .ok()
+            .filter(|duration| *duration > 0)
+            .ok_or_else(|| format!("invalid betting time duration in {:?}", item))?;
+        let house_edge_pct = house_edge_pct
+            .parse::<f32>()
+            .ok()
+            .filter(|house_edge_pct| *house_edge_pct > 0.0 && *house_edge_pct < 1.0)
+            .ok_or_else(|| format!("invalid house edge in {:?}", item))?;
+        let min_bet = min_bet
+            .parse::<u64>()
+            .map_err(|_| format!("invalid min bet in {:?}", item))?;
+        let max_bet = max_bet
+            .parse::<u64>()
+            .map_err(|_| format!("invalid max bet in {:?}", item))?;
+        let max_win = max_win
+            .parse::<u64>()
+            .map_err(|_| format!("invalid max win in {:?}", item))?;
+        rooms.push(RoomSettings {
+            id: id.to_string(),
+            currency: currency.to_string(),
+            betting_time_duration,
+            house_edge_pct,
+            bet_limits: BetLimits::new(min_bet, max_bet, max_win)?,
+        });
+    }
+    Ok(rooms)
+}
+
+/// Rooms of the server, with the number of players in each room
+#[derive(Debug, Clone)]
+pub struct RoomRegistry {
+    rooms: Arc<Vec<RegisteredRoom>>,
+}
+
+#[derive(Debug)]
+struct RegisteredRoom {
+    settings: RoomSettings,
+    players_online: AtomicU32,
+}
+
+impl RoomRegistry {
+    pub fn new(rooms: Vec<RoomSettings>) -> Self {
+        Self {
+            rooms: Arc::new(
+                rooms
+                    .into_iter()
+                    .map(|settings| RegisteredRoom {
+                        settings,
+                        players_online: AtomicU32::new(0),
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Rooms in config order, with the number of players in them
+    pub fn list(&self) -> Vec<(RoomSettings, u32)> {
+        self.rooms
+            .iter()
+            .map(|room| {
+                (
+                    room.settings.clone(),
+                    room.players_online.load(Ordering::SeqCst),
+                )
+            })
+            .collect()
+    }
+
+    /// First room of the currency, players join it when they don't ask for a room
+    pub fn default_room_of(&self, currency: &str) -> Option<&RoomSettings> {
+        self.rooms
+            .iter()
+            .map(|room| &room.settings)
+            .find(|settings| settings.currency == currency)
+    }
+
+    /// Returns the number of players in the room
+    pub fn player_joined(&self, room_id: &str) -> u32 {
+        self.find(room_id).map_or(0, |room| {
+            room.players_online.fetch_add(1, Ordering::SeqCst) + 1
+        })
+    }
+
+    /// Returns the number of players in the room
+    pub fn player_left(&self, room_id: &str) -> u32 {
+        self.find(room_id).map_or(0, |room| {
+            room.players_online
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                    Some(count.saturating_sub(1))
+                })
+                .unwrap_or_default()
+                .saturating_sub(1)
+        })
+    }
+
+    fn find(&self, room_id: &str) -> Option<&RegisteredRoom> {
+        self.rooms.iter().find(|room| room.settings.id == room_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rooms() {
+        let rooms =
+            parse_rooms("fun:FUN:5:0.03:100:100000:1000000, eur:EUR:10:0.01:10:1000:5000").unwrap();
+        assert_eq!(rooms.len(), 2);
+        assert_eq!(rooms[0].id, "fun");
+        assert_eq!(rooms[0].betting_time_duration, 5);
+        assert_eq!(rooms[1].currency, "EUR");
+        assert_eq!(rooms[1].house_edge_pct, 0.01);
+        assert_eq!(rooms[1].bet_limits, BetLimits::new(10, 1000, 5000).unwrap());
+        assert!(parse_rooms("").unwrap().is_empty());
+
+        assert!(parse_rooms("fun:FUN:5:0.03").is_err());
+        assert!(parse_rooms("fun:FUN:0:0.03:100:1000:1000").is_err());
+        assert!(parse_rooms("fun:FUN:5:1.5:100:1000:1000").is_err());
+        assert!(parse_rooms("fun:FUN:5:0.03:1000:100:1000").is_err());
+        assert!(parse_rooms(":FUN:5:0.03:100:1000:1000").is_err());
+        assert!(parse_rooms("fun:FUN:5:0.03:100:1000:1000,fun:EUR:5:0.03:100:1000:1000").is_err());
+    }
+
+    #[test]
+    fn test_registry_counts_players_per_room() {
+        let room_registry = RoomRegistry::new(
+            parse_rooms("fun:FUN:5:0.03:100:1000:1000,fast:FUN:2:0.03:100:1000:1000").unwrap(),
+        );
+        assert_eq!(room_registry.default_room_of("FUN").unwrap().id, "fun");
+        assert!(room_registry.default_room_of("EUR").is_none());
+
+        assert_eq!(room_registry.player_joined("fun"), 1);
+        assert_eq!(room_registry.player_joined("fun"), 2);
+        assert_eq!(room_registry.player_joined("fast"), 1);
+        assert_eq!(room_registry.player_left("fun"), 1);
+        assert_eq!(room_registry.player_left("fast"), 0);
+        // never goes below zero
+        assert_eq!(room_registry.player_left("fast"), 0);
+        assert_eq!(room_registry.player_joined("unknown"), 0);
+
+        let players_online: Vec<(String, u32)> = room_registry
+            .list()
+            .into_iter()
+            .map(|(settings, players_online)| (settings.id, players_online))
+            .collect();
+        assert_eq!(
+            players_online,
+            vec![("fun".to_string(), 1), ("fast".to_string(), 0)]
+        );
+    }
+}
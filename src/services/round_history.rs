@@ -0,0 +1,277 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoundRecord {
+    pub round_id: u32,
+    /// room the round was played in
+    pub room_id: String,
+    #[serde(with = "timestamp_millis")]
+    pub started_at: DateTime<Utc>,
+    #[serde(with = "timestamp_millis")]
+    pub ended_at: DateTime<Utc>,
+    /// final multiplier
+    pub crash_point: u32,
+    /// sha256 of the server seed, published before the round
+    pub server_seed_hash: String,
+    /// terminal hash of the seed chain, the server seed belongs to
+    pub seed_chain_hash: String,
+    pub server_seed: String,
+    pub client_seed: String,
+    pub client_seed_inputs: Vec<String>,
+    pub client_seed_salt: String,
+    pub house_edge_pct: f32,
+    pub total_wagered: u64,
+    pub total_paid_out: u64,
+    /// number of players, who placed a bet
+    pub player_count: u32,
+}
+
+/// Timestamps are stored in unix milliseconds, like the ledger entries
+mod timestamp_millis {
+    use chrono::{DateTime, TimeZone, Utc};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        time: &DateTime<Utc>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(time.timestamp_millis())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<DateTime<Utc>, D::Error> {
+        let millis = i64::deserialize(deserializer)?;
+        Utc.timestamp_millis_opt(millis)
+            .single()
+            .ok_or_else(|| D::Error::custom("invalid timestamp"))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RoundHistory {
+    rounds: Arc<RwLock<VecDeque<RoundRecord>>>,
+    /// number of finished rounds kept
+    max_rounds: usize,
+    file: Option<Arc<Mutex<File>>>,
+}
+
+impl RoundHistory {
+    /// History kept in memory only, it is lost on restart
+    pub fn new(max_rounds: usize) -> Self {
+        Self {
+            rounds: Arc::new(RwLock::new(VecDeque::with_capacity(max_rounds))),
+            max_rounds,
+            file: None,
+        }
+    }
+
+    /// History persisted to a JSON lines file, the newest rounds are loaded from the file.
+    /// Rounds, that don't fit in the history anymore, are removed from the file.
+    pub fn open(file_path: &str, max_rounds: usize) -> Self {
+        let mut rounds = VecDeque::with_capacity(max_rounds);
+        let mut line_count = 0;
+        if let Ok(data) = fs::read_to_string(file_path) {
+            for line in data.lines().filter(|line| !line.trim().is_empty()) {
+                line_count += 1;
+                match serde_json::from_str::<RoundRecord>(line) {
+                    Ok(record) => {
+                        if rounds.len() >= max_rounds {
+                            rounds.pop_front();
+                        }
+                        rounds.push_back(record);
+                    }
+                    Err(err) => warn!("skipping invalid round record! {:?}", err),
+                }
+            }
+            info!("loaded {:?} rounds from {:?}", rounds.len(), file_path);
+        }
+
+        if let Some(dir) = Path::new(file_path).parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        if line_count > rounds.len() {
+            // write to a temp file first, so the history is never half written
+            let data: String = rounds
+                .iter()
+                .map(|record| {
+                    serde_json::to_string(record).expect("round record is serializable") + "\n"
+                })
+                .collect();
+            let tmp_file_path = format!("{}.tmp", file_path);
+            let result =
+                fs::write(&tmp_file_path, data).and_then(|_| fs::rename(&tmp_file_path, file_path));
+            if let Err(err) = result {
+                warn!("unable to compact round history file! {:?}", err);
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(file_path)
+            .expect("round history file must be writable");
+
+        Self {
+            rounds: Arc::new(RwLock::new(rounds)),
+            max_rounds,
+            file: Some(Arc::new(Mutex::new(file))),
+        }
+    }
+
+    /// Records a finished round, the oldest round is dropped when the history is full.
+    pub fn add(&self, record: RoundRecord) {
+        if let Some(file) = &self.file {
+            let line = serde_json::to_string(&record).expect("round record is serializable");
+            let mut file = file.lock().unwrap();
+            if let Err(err) = writeln!(file, "{}", line) {
+                warn!(
+                    "unable to write round record! {:?} {:?}",
+                    record.round_id, err
+                );
+            }
+        }
+
+        let mut rounds = self.rounds.write().unwrap();
+        if rounds.len() >= self.max_rounds {
+            rounds.pop_front();
+        }
+        rounds.push_back(record);
+    }
+
+    pub fn find(&self, round_id: u32) -> Option<RoundRecord> {
+        let rounds = self.rounds.read().unwrap();
+        rounds
+            .iter()
+            .rev()
+            .find(|record| record.round_id == round_id)
+            .cloned()
+    }
+
+    /// Page of rounds (starting from 1), newest first. Returns the rounds with the total number of rounds.
+    pub fn list(&self, page: usize, page_size: usize) -> (Vec<RoundRecord>, usize) {
+        let rounds = self.rounds.read().unwrap();
+        let page_rounds = rounds
+            .iter()
+            .rev()
+            .skip(page.saturating_sub(1).saturating_mul(page_size))
+            .take(page_size)
+            .cloned()
+            .collect();
+        (page_rounds, rounds.len())
+    }
+
+    /// Crash points of the last rounds in the room, newest first
+    pub fn recent_crash_points(&self, room_id: &str, count: usize) -> Vec<u32> {
+        let rounds = self.rounds.read().unwrap();
+        rounds
+            .iter()
+            .rev()
+            .filter(|record| record.room_id == room_id)
+            .take(count)
+            .map(|record| record.crash_point)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn round_record(round_id: u32) -> RoundRecord {
+        RoundRecord {
+            round_id,
+            room_id: format!("room-{}", round_id % 2),
+            started_at: Utc::now(),
+            ended_at: Utc::now(),
+            crash_point: round_id * 10,
+            server_seed_hash: String::new(),
+            seed_chain_hash: String::new(),
+            server_seed: String::new(),
+            client_seed: String::new(),
+            client_seed_inputs: Vec::new(),
+            client_seed_salt: String::new(),
+            house_edge_pct: 0.03,
+            total_wagered: 0,
+            total_paid_out: 0,
+            player_count: 0,
+        }
+    }
+
+    #[test]
+    fn test_history_keeps_newest_rounds() {
+        let round_history = RoundHistory::new(3);
+        for round_id in 1..=5 {
+            round_history.add(round_record(round_id));
+        }
+
+        assert!(round_history.find(2).is_none());
+        assert_eq!(round_history.find(3).unwrap().round_id, 3);
+        assert_eq!(round_history.recent_crash_points("room-1", 5), vec![50, 30]);
+        assert_eq!(round_history.recent_crash_points("room-0", 1), vec![40]);
+        assert!(round_history.recent_crash_points("unknown", 5).is_empty());
+
+        let (rounds, total) = round_history.list(1, 2);
+        assert_eq!(total, 3);
+        assert_eq!(
+            rounds.iter().map(|r| r.round_id).collect::<Vec<_>>(),
+            vec![5, 4]
+        );
+
+        let (rounds, _) = round_history.list(2, 2);
+        assert_eq!(
+            rounds.iter().map(|r| r.round_id).collect::<Vec<_>>(),
+            vec![3]
+        );
+        assert!(round_history.list(3, 2).0.is_empty());
+        assert!(round_history.list(usize::MAX, 2).0.is_empty());
+    }
+
+    #[test]
+    fn test_history_is_restored_after_restart() {
+        let file_path = env::temp_dir().join(format!(
+            "crash-server-round-history-{}.jsonl",
+            std::process::id()
+        ));
+        let file_path = file_path.to_string_lossy().to_string();
+        let _ = fs::remove_file(&file_path);
+
+        let round_history = RoundHistory::open(&file_path, 3);
+        for round_id in 1..=5 {
+            round_history.add(round_record(round_id));
+        }
+        let started_at = round_history.find(5).unwrap().started_at;
+        drop(round_history);
+
+        let round_history = RoundHistory::open(&file_path, 3);
+        assert!(round_history.find(2).is_none());
+        let record = round_history.find(5).unwrap();
+        assert_eq!(record.crash_point, 50);
+        assert_eq!(
+            record.started_at.timestamp_millis(),
+            started_at.timestamp_millis()
+        );
+        assert_eq!(round_history.list(1, 10).1, 3);
+
+        // rounds, that don't fit in the history, are removed from the file
+        round_history.add(round_record(6));
+        drop(round_history);
+        let round_history = RoundHistory::open(&file_path, 2);
+        assert_eq!(round_history.recent_crash_points("room-1", 5), vec![50]);
+        assert_eq!(fs::read_to_string(&file_path).unwrap().lines().count(), 2);
+
+        let _ = fs::remove_file(&file_path);
+    }
+}
@@ -0,0 +1,167 @@
+use chrono::{DateTime, Utc};
+
+/// Phase of a round, rounds go through the phases in this order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundPhase {
+    /// no round, waiting for players
+    Idle,
+    /// bets are accepted
+    Betting,
+    /// bets are closed, round outcome is being computed
+    Launching,
+    /// multiplier is growing, bets can be crashed out
+    Running,
+    /// multiplier reached the crash point
+    Crashed,
+    /// bets of the round are settled
+    Settling,
+    /// result of the round is shown, before the next round starts
+    Cooldown,
+}
+
+impl From<RoundPhase> for u8 {
+    fn from(phase: RoundPhase) -> u8 {
+        // 0 to 2 are the game states sent before rounds had phases
+        match phase {
+            RoundPhase::Idle => 0,
+            RoundPhase::Betting => 1,
+            RoundPhase::Running => 2,
+            RoundPhase::Launching => 3,
+            RoundPhase::Crashed => 4,
+            RoundPhase::Settling => 5,
+            RoundPhase::Cooldown => 6,
+        }
+    }
+}
+
+impl RoundPhase {
+    fn can_change_to(self, next: RoundPhase) -> bool {
+        matches!(
+            (self, next),
+            (RoundPhase::Idle, RoundPhase::Betting)
+                | (RoundPhase::Betting, RoundPhase::Launching)
+                // everyone left the room before the round was launched
+                | (RoundPhase::Betting, RoundPhase::Idle)
+                | (RoundPhase::Launching, RoundPhase::Running)
+                | (RoundPhase::Running, RoundPhase::Crashed)
+                | (RoundPhase::Crashed, RoundPhase::Settling)
+                // round outcome couldn't be computed, bets are refunded
+                | (RoundPhase::Launching, RoundPhase::Settling)
+                | (RoundPhase::Settling, RoundPhase::Cooldown)
+                | (RoundPhase::Cooldown, RoundPhase::Idle)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidPhaseChange {
+    pub from: RoundPhase,
+    pub to: RoundPhase,
+}
+
+/// Phase of the current round, with the time each phase of the round started
+#[derive(Debug)]
+pub struct RoundStateMachine {
+    phase: RoundPhase,
+    /// phases of the current round, in the order they started
+    phase_started_at: Vec<(RoundPhase, DateTime<Utc>)>,
+}
+
+impl RoundStateMachine {
+    pub fn new() -> Self {
+        Self {
+            phase: RoundPhase::Idle,
+            phase_started_at: vec![(RoundPhase::Idle, Utc::now())],
+        }
+    }
+
+    pub fn phase(&self) -> RoundPhase {
+        self.phase
+    }
+
+    /// Moves to the next phase, a new round starts with the betting phase
+    pub fn change_to(&mut self, next: RoundPhase) -> Result<(), InvalidPhaseChange> {
+        if !self.phase.can_change_to(next) {
+            return Err(InvalidPhaseChange {
+                from: self.phase,
+                to: next,
+            });
+        }
+
+        if next == RoundPhase::Betting {
+            self.phase_started_at.clear();
+        }
+        self.phase = next;
+        self.phase_started_at.push((next, Utc::now()));
+        Ok(())
+    }
+
+    /// Time the phase started in the current round, None if the round didn't reach it
+    pub fn started_at(&self, phase: RoundPhase) -> Option<DateTime<Utc>> {
+        self.phase_started_at
+            .iter()
+            .rev()
+            .find(|(started_phase, _)| *started_phase == phase)
+            .map(|(_, started_at)| *started_at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_goes_through_phases_in_order() {
+        let mut round = RoundStateMachine::new();
+        assert_eq!(round.phase(), RoundPhase::Idle);
+
+        for phase in [
+            RoundPhase::Betting,
+            RoundPhase::Launching,
+            RoundPhase::Running,
+            RoundPhase::Crashed,
+            RoundPhase::Settling,
+            RoundPhase::Cooldown,
+            RoundPhase::Idle,
+            RoundPhase::Betting,
+        ] {
+            round.change_to(phase).unwrap();
+            assert_eq!(round.phase(), phase);
+        }
+
+        // phases of the previous round are forgotten, when the next round starts
+        assert!(round.started_at(RoundPhase::Betting).is_some());
+        assert!(round.started_at(RoundPhase::Running).is_none());
+    }
+
+    #[test]
+    fn test_invalid_phase_changes_are_rejected() {
+        let mut round = RoundStateMachine::new();
+        assert_eq!(
+            round.change_to(RoundPhase::Running),
+            Err(InvalidPhaseChange {
+                from: RoundPhase::Idle,
+                to: RoundPhase::Running
+            })
+        );
+        assert_eq!(round.phase(), RoundPhase::Idle);
+
+        round.change_to(RoundPhase::Betting).unwrap();
+        assert!(round.change_to(RoundPhase::Betting).is_err());
+        assert!(round.change_to(RoundPhase::Crashed).is_err());
+
+        // round is settled without running, when its outcome couldn't be computed
+        round.change_to(RoundPhase::Launching).unwrap();
+        round.change_to(RoundPhase::Settling).unwrap();
+        assert!(round.started_at(RoundPhase::Running).is_none());
+        assert!(round.change_to(RoundPhase::Betting).is_err());
+        // next round doesn't start before the cooldown
+        assert!(round.change_to(RoundPhase::Idle).is_err());
+        round.change_to(RoundPhase::Cooldown).unwrap();
+        round.change_to(RoundPhase::Idle).unwrap();
+
+        // round isn't launched, when the room is empty
+        round.change_to(RoundPhase::Betting).unwrap();
+        round.change_to(RoundPhase::Idle).unwrap();
+    }
+}
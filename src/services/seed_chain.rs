@@ -0,0 +1,270 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::crash_game_math::{sha256, CrashGameMath};
+
+/// every n-th seed is kept in memory, so seeds don't have to be re-hashed from the start
+const CHECKPOINT_INTERVAL: u32 = 1000;
+
+const FIRST_ROUND_ID: u32 = 32315;
+
+/// Reverse hash chain of server seeds.
+///
+/// `seed[0] = sha256(secret)`, `seed[i + 1] = sha256(seed[i])`. The last hash `sha256(seed[length - 1])`
+/// is published up front, and rounds consume the chain backwards, so `sha256(round seed)` is always
+/// the seed of the previous round (or the published hash, for the first round of the chain).
+#[derive(Debug, Clone)]
+pub struct SeedChain {
+    state: Arc<Mutex<ChainState>>,
+    state_file_path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChainState {
+    secret: String,
+    length: u32,
+    /// number of seeds consumed from the chain
+    position: u32,
+    /// id of the last round, that consumed a seed
+    round_id: u32,
+    #[serde(skip)]
+    checkpoints: Vec<String>,
+    #[serde(skip)]
+    terminal_hash: String,
+}
+
+impl ChainState {
+    fn new(length: u32, round_id: u32) -> Self {
+        let mut state = Self {
+            secret: CrashGameMath::generate_seed(),
+            length,
+            position: 0,
+            round_id,
+            checkpoints: Vec::new(),
+            terminal_hash: String::new(),
+        };
+        state.build_checkpoints();
+        state
+    }
+
+    fn build_checkpoints(&mut self) {
+        self.checkpoints.clear();
+        let mut seed = sha256(&self.secret);
+        for i in 0..self.length {
+            if i % CHECKPOINT_INTERVAL == 0 {
+                self.checkpoints.push(seed.clone());
+            }
+            seed = sha256(&seed);
+        }
+        self.terminal_hash = seed;
+    }
+
+    fn seed_at(&self, index: u32) -> String {
+        let checkpoint = index / CHECKPOINT_INTERVAL;
+        let mut seed = self.checkpoints[checkpoint as usize].clone();
+        for _ in (checkpoint * CHECKPOINT_INTERVAL)..index {
+            seed = sha256(&seed);
+        }
+        seed
+    }
+}
+
+impl SeedChain {
+    /// Continues the chain stored in the state file, or creates a new chain if there is none.
+    ///
+    /// Panics if the state file can't be read, as a new chain would overwrite the secret
+    /// behind the published hash.
+    pub fn load_or_create(length: u32, state_file_path: &str) -> Self {
+        let state = match fs::read_to_string(state_file_path) {
+            Ok(data) => {
+                let mut state = serde_json::from_str::<ChainState>(&data).unwrap_or_else(|err| {
+                    panic!(
+                        "seed chain state file {:?} is invalid, restore or remove it! {:?}",
+                        state_file_path, err
+                    )
+                });
+                info!(
+                    "continuing seed chain at position {:?}/{:?}",
+                    state.position, state.length
+                );
+                state.build_checkpoints();
+                state
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                ChainState::new(length, FIRST_ROUND_ID - 1)
+            }
+            Err(err) => panic!(
+                "seed chain state file {:?} can't be read! {:?}",
+                state_file_path, err
+            ),
+        };
+
+        let seed_chain = Self {
+            state: Arc::new(Mutex::new(state)),
+            state_file_path: state_file_path.to_string(),
+        };
+        seed_chain
+            .save(&seed_chain.state.lock().unwrap())
+            .expect("seed chain state file must be writable");
+        seed_chain
+    }
+
+    /// Hash published up front, that the chain terminates in
+    pub fn terminal_hash(&self) -> String {
+        self.state.lock().unwrap().terminal_hash.clone()
+    }
+
+    /// Consumes the next seed of the chain, returns it with the id of the round it belongs to.
+    /// A new chain is created, when the current one is used up.
+    ///
+    /// The seed is returned only when the new position is saved, otherwise the chain would
+    /// hand out the revealed seeds again after a restart.
+    pub fn next_round(&self) -> io::Result<(u32, String)> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.position >= state.length {
+            warn!("seed chain is used up, creating a new chain!");
+            *state = ChainState::new(state.length, state.round_id);
+        }
+
+        state.position += 1;
+        state.round_id += 1;
+        if let Err(err) = self.save(&state) {
+            state.position -= 1;
+            state.round_id -= 1;
+            return Err(err);
+        }
+
+        let seed = state.seed_at(state.length - state.position);
+        Ok((state.round_id, seed))
+    }
+
+    fn save(&self, state: &ChainState) -> io::Result<()> {
+        let data = serde_json::to_string(state).expect("seed chain state is serializable");
+        let tmp_file_path = format!("{}.tmp", self.state_file_path);
+
+        if let Some(dir) = Path::new(&self.state_file_path).parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+
+        // write to a temp file first, so the state file is never half written
+        let result = fs::write(&tmp_file_path, data)
+            .and_then(|_| fs::rename(&tmp_file_path, &self.state_file_path));
+        if let Err(err) = &result {
+            warn!("unable to save seed chain state! {:?}", err);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn temp_state_file_path(name: &str) -> String {
+        let path =
+            env::temp_dir().join(format!("crash-server-{}-{}.json", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn test_seeds_are_consumed_backwards() {
+        let state_file_path = temp_state_file_path("seed-chain-backwards");
+        let seed_chain = SeedChain::load_or_create(2500, &state_file_path);
+
+        let mut previous_hash = seed_chain.terminal_hash();
+        let mut previous_round_id = FIRST_ROUND_ID - 1;
+        for _ in 0..2500 {
+            let (round_id, seed) = seed_chain.next_round().unwrap();
+            assert_eq!(sha256(&seed), previous_hash);
+            assert_eq!(round_id, previous_round_id + 1);
+            previous_hash = seed;
+            previous_round_id = round_id;
+        }
+
+        let _ = fs::remove_file(&state_file_path);
+    }
+
+    #[test]
+    fn test_chain_continues_after_restart() {
+        let state_file_path = temp_state_file_path("seed-chain-restart");
+
+        let seed_chain = SeedChain::load_or_create(100, &state_file_path);
+        let terminal_hash = seed_chain.terminal_hash();
+        let (_, last_seed) = seed_chain.next_round().unwrap();
+        drop(seed_chain);
+
+        let seed_chain = SeedChain::load_or_create(100, &state_file_path);
+        assert_eq!(seed_chain.terminal_hash(), terminal_hash);
+
+        let (round_id, seed) = seed_chain.next_round().unwrap();
+        assert_eq!(round_id, FIRST_ROUND_ID + 1);
+        assert_eq!(sha256(&seed), last_seed);
+
+        let _ = fs::remove_file(&state_file_path);
+    }
+
+    #[test]
+    fn test_new_chain_is_created_when_used_up() {
+        let state_file_path = temp_state_file_path("seed-chain-used-up");
+        let seed_chain = SeedChain::load_or_create(3, &state_file_path);
+        let terminal_hash = seed_chain.terminal_hash();
+
+        for _ in 0..3 {
+            seed_chain.next_round().unwrap();
+        }
+        let (round_id, seed) = seed_chain.next_round().unwrap();
+
+        assert_ne!(seed_chain.terminal_hash(), terminal_hash);
+        assert_eq!(sha256(&seed), seed_chain.terminal_hash());
+        assert_eq!(round_id, FIRST_ROUND_ID + 3);
+
+        let _ = fs::remove_file(&state_file_path);
+    }
+
+    #[test]
+    fn test_seed_is_not_handed_out_when_state_is_not_saved() {
+        let state_file_path = temp_state_file_path("seed-chain-unsaved");
+        let seed_chain = SeedChain::load_or_create(100, &state_file_path);
+        let terminal_hash = seed_chain.terminal_hash();
+
+        // state file can't be replaced by a directory
+        fs::remove_file(&state_file_path).unwrap();
+        fs::create_dir(&state_file_path).unwrap();
+        assert!(seed_chain.next_round().is_err());
+
+        fs::remove_dir(&state_file_path).unwrap();
+        let (round_id, seed) = seed_chain.next_round().unwrap();
+        assert_eq!(round_id, FIRST_ROUND_ID);
+        assert_eq!(sha256(&seed), terminal_hash);
+
+        let _ = fs::remove_file(&state_file_path);
+        let _ = fs::remove_file(format!("{}.tmp", state_file_path));
+    }
+
+    #[test]
+    #[should_panic(expected = "seed chain state file")]
+    fn test_invalid_state_file_is_not_overwritten() {
+        let state_file_path = temp_state_file_path("seed-chain-invalid");
+        fs::write(&state_file_path, "{\"secret\":").unwrap();
+
+        let result = std::panic::catch_unwind(|| SeedChain::load_or_create(100, &state_file_path));
+        assert_eq!(
+            fs::read_to_string(&state_file_path).unwrap(),
+            "{\"secret\":"
+        );
+        let _ = fs::remove_file(&state_file_path);
+        std::panic::resume_unwind(result.unwrap_err());
+    }
+}
@@ -0,0 +1,281 @@
+use std::{fmt::Debug, time::Duration};
+
+use derive_more::Display;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum WalletError {
+    InvalidToken,
+    UnknownPlayer,
+    InsufficientFunds,
+    /// wallet can't be reached, or sent an unexpected response
+    Unavailable,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalletSession {
+    pub player_id: String,
+    pub balance: u64,
+}
+
+/// Operator's wallet, that holds the balance of REAL mode players ("seamless wallet").
+/// Every balance change carries a transaction id, so the wallet can detect retries.
+pub trait WalletProvider: Debug + Send + Sync {
+    /// Exchanges the session token given by the operator for the player id
+    fn authenticate(&self, token: &str) -> Result<WalletSession, WalletError>;
+
+    fn balance(&self, player_id: &str) -> Result<u64, WalletError>;
+
+    /// Takes the bet amount, returns the new balance
+    fn debit(
+        &self,
+        player_id: &str,
+        tx_id: &str,
+        round_id: u32,
+        amount: u64,
+    ) -> Result<u64, WalletError>;
+
+    /// Pays the win amount, returns the new balance
+    fn credit(
+        &self,
+        player_id: &str,
+        tx_id: &str,
+        round_id: u32,
+        amount: u64,
+    ) -> Result<u64, WalletError>;
+
+    /// Reverts the debit with the given transaction id, returns the new balance
+    fn rollback(
+        &self,
+        player_id: &str,
+        tx_id: &str,
+        debit_tx_id: &str,
+        round_id: u32,
+    ) -> Result<u64, WalletError>;
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuthenticateRequestData<'a> {
+    token: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BalanceRequestData<'a> {
+    player_id: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TransactionRequestData<'a> {
+    player_id: &'a str,
+    transaction_id: &'a str,
+    round_id: u32,
+    amount: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RollbackRequestData<'a> {
+    player_id: &'a str,
+    transaction_id: &'a str,
+    /// transaction id of the debit, that is reverted
+    reference_transaction_id: &'a str,
+    round_id: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AuthenticateResponseData {
+    player_id: String,
+    balance: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BalanceResponseData {
+    balance: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorResponseData {
+    error: String,
+}
+
+/// Wallet reached with JSON POST requests to `{base_url}/authenticate`, `/balance`, `/debit`,
+/// `/credit` and `/rollback`. Failed requests answer with a 4xx status and `{"error": "..."}`.
+/// Requests are blocking, and are limited by the timeout.
+#[derive(Debug)]
+pub struct HttpWalletProvider {
+    base_url: String,
+    api_key: String,
+    agent: ureq::Agent,
+}
+
+impl HttpWalletProvider {
+    pub fn new(base_url: &str, api_key: &str, timeout_ms: u64) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_millis(timeout_ms))
+                .build(),
+        }
+    }
+
+    fn post<T: for<'de> Deserialize<'de>>(
+        &self,
+        path: &str,
+        data: impl Serialize,
+    ) -> Result<T, WalletError> {
+        let url = format!("{}{}", self.base_url, path);
+        let result = self
+            .agent
+            .post(&url)
+            .set("X-Api-Key", &self.api_key)
+            .send_json(data);
+
+        match result {
+            Ok(response) => response.into_json::<T>().map_err(|err| {
+                warn!("invalid wallet response from {:?}! {:?}", url, err);
+                WalletError::Unavailable
+            }),
+            Err(ureq::Error::Status(status, response)) => {
+                let error = response
+                    .into_json::<ErrorResponseData>()
+                    .map(|data| data.error)
+                    .unwrap_or_default();
+                warn!("wallet error from {:?}! {:?} {:?}", url, status, error);
+                Err(match error.as_str() {
+                    "INVALID_TOKEN" => WalletError::InvalidToken,
+                    "UNKNOWN_PLAYER" => WalletError::UnknownPlayer,
+                    "INSUFFICIENT_FUNDS" => WalletError::InsufficientFunds,
+                    _ => WalletError::Unavailable,
+                })
+            }
+            Err(err) => {
+                warn!("wallet is not reachable {:?}! {:?}", url, err);
+                Err(WalletError::Unavailable)
+            }
+        }
+    }
+}
+
+impl WalletProvider for HttpWalletProvider {
+    fn authenticate(&self, token: &str) -> Result<WalletSession, WalletError> {
+        let data: AuthenticateResponseData =
+            self.post("/authenticate", AuthenticateRequestData { token })?;
+        Ok(WalletSession {
+            player_id: data.player_id,
+            balance: data.balance,
+        })
+    }
+
+    fn balance(&self, player_id: &str) -> Result<u64, WalletError> {
+        let data: BalanceResponseData = self.post("/balance", BalanceRequestData { player_id })?;
+        Ok(data.balance)
+    }
+
+    fn debit(
+        &self,
+        player_id: &str,
+        tx_id: &str,
+        round_id: u32,
+        amount: u64,
+    ) -> Result<u64, WalletError> {
+        let request_data = TransactionRequestData {
+            player_id,
+            transaction_id: tx_id,
+            round_id,
+            amount,
+        };
+        let data: BalanceResponseData = self.post("/debit", request_data)?;
+        Ok(data.balance)
+    }
+
+    fn credit(
+        &self,
+        player_id: &str,
+        tx_id: &str,
+        round_id: u32,
+        amount: u64,
+    ) -> Result<u64, WalletError> {
+        let request_data = TransactionRequestData {
+            player_id,
+            transaction_id: tx_id,
+            round_id,
+            amount,
+        };
+        let data: BalanceResponseData = self.post("/credit", request_data)?;
+        Ok(data.balance)
+    }
+
+    fn rollback(
+        &self,
+        player_id: &str,
+        tx_id: &str,
+        debit_tx_id: &str,
+        round_id: u32,
+    ) -> Result<u64, WalletError> {
+        let request_data = RollbackRequestData {
+            player_id,
+            transaction_id: tx_id,
+            reference_transaction_id: debit_tx_id,
+            round_id,
+        };
+        let data: BalanceResponseData = self.post("/rollback", request_data)?;
+        Ok(data.balance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::mock_wallet_server::MockWalletServer;
+
+    #[test]
+    fn test_http_wallet_against_mock_server() {
+        let server = MockWalletServer::start();
+        server.add_player("token-1", "player-1", 1_000);
+        let wallet = HttpWalletProvider::new(&server.url(), "key", 2_000);
+
+        assert_eq!(
+            wallet.authenticate("token-1"),
+            Ok(WalletSession {
+                player_id: "player-1".to_string(),
+                balance: 1_000
+            })
+        );
+        assert_eq!(
+            wallet.authenticate("token-2"),
+            Err(WalletError::InvalidToken)
+        );
+
+        assert_eq!(wallet.debit("player-1", "1:player-1:bet", 1, 400), Ok(600));
+        // repeated transaction id isn't applied again
+        assert_eq!(wallet.debit("player-1", "1:player-1:bet", 1, 400), Ok(600));
+        assert_eq!(
+            wallet.debit("player-1", "2:player-1:bet", 2, 700),
+            Err(WalletError::InsufficientFunds)
+        );
+        assert_eq!(
+            wallet.credit("player-1", "1:player-1:win", 1, 800),
+            Ok(1_400)
+        );
+        assert_eq!(
+            wallet.rollback("player-1", "1:player-1:refund", "1:player-1:bet", 1),
+            Ok(1_800)
+        );
+        assert_eq!(wallet.balance("player-1"), Ok(1_800));
+        assert_eq!(wallet.balance("player-2"), Err(WalletError::UnknownPlayer));
+
+        let unreachable_wallet = HttpWalletProvider::new("http://127.0.0.1:1", "key", 500);
+        assert_eq!(
+            unreachable_wallet.balance("player-1"),
+            Err(WalletError::Unavailable)
+        );
+    }
+}
@@ -1,12 +1,15 @@
-use flatbuffers::FlatBufferBuilder;
+use flatbuffers::{FlatBufferBuilder, WIPOffset};
 
 use crate::{
     generated::game_schema_generated::gameplay_fbdata::{
-        root_as_game_request_event, BetResponse, BetResponseArgs, BettingTimerStarted, BettingTimerStartedArgs, BettingTimerUpdate, BettingTimerUpdateArgs, CrashOutResponse, CrashOutResponseArgs, GameFinished, GameFinishedArgs, GameResponseEvent, GameResponseEventArgs, GameStarted, GameStartedArgs, GameUpdate, GameUpdateArgs, JoinGameResponse, JoinGameResponseArgs, RemotePlayerBetsPlaced, RemotePlayerBetsPlacedArgs, RemotePlayerCrashOut, RemotePlayerCrashOutArgs, RemotePlayerJoined, RemotePlayerJoinedArgs, RemotePlayerLeft, RemotePlayerLeftArgs, RequestMessages, ResponseMessage
+        root_as_game_request_event, BetError, BetErrorArgs, BetResponse, BetResponseArgs, BettingTimerStarted, BettingTimerStartedArgs, BettingTimerUpdate, BettingTimerUpdateArgs, CrashOutError, CrashOutErrorArgs, CrashOutResponse, CrashOutResponseArgs, GameFinished, GameFinishedArgs, GameLoopStateChanged, GameLoopStateChangedArgs, GameResponseEvent, GameResponseEventArgs, GameStarted, GameStartedArgs, GameUpdate, GameUpdateArgs, JoinGameError, JoinGameErrorArgs, JoinGameResponse, JoinGameResponseArgs, RemotePlayerBetsPlaced, RemotePlayerBetsPlacedArgs, RemotePlayerCrashOut, RemotePlayerCrashOutArgs, RemotePlayerJoined, RemotePlayerJoinedArgs, RemotePlayerLeft, RemotePlayerLeftArgs, RefillBalanceError, RefillBalanceErrorArgs, RefillBalanceResponse, RefillBalanceResponseArgs, RequestMessages, ResponseMessage, RoundPhaseChanged, RoundPhaseChangedArgs, RoundResult, RoundResultArgs, RoundSummary, RoundSummaryArgs, RoundWinner as RoundWinnerData, RoundWinnerArgs, WaitingForPlayers, WaitingForPlayersArgs
     },
-    services::peer::ClientData,
+    services::{currency::BetLimits, message_types::RoundWinner, peer::ClientData},
 };
 
+/// longer client seeds are truncated
+const MAX_CLIENT_SEED_LENGTH: usize = 64;
+
 pub fn parse_gameplay_data(buf: &[u8]) -> ClientData {
     let gameplay = root_as_game_request_event(buf).unwrap();
     let event_type = gameplay.msg_type();
@@ -16,22 +19,43 @@ pub fn parse_gameplay_data(buf: &[u8]) -> ClientData {
             if let Some(auth_data) = gameplay.msg_as_join_game_request() {
                 let player_uuid = auth_data.player_uuid().unwrap_or_else(|| "");
                 let jwt_token = auth_data.jwt_token().unwrap_or_else(|| "");
+                let client_seed = auth_data.client_seed().unwrap_or("");
+                let room_id = auth_data.room_id().unwrap_or("");
 
                 return ClientData::JoinGameRequest {
                     jwt_token: jwt_token.to_string(),
                     player_uuid: player_uuid.to_string(),
+                    client_seed: sanitize_client_seed(client_seed),
+                    room_id: room_id.trim().to_string(),
+                };
+            }
+        }
+        RequestMessages::SetClientSeedRequest => {
+            if let Some(seed_data) = gameplay.msg_as_set_client_seed_request() {
+                let client_seed = seed_data.client_seed().unwrap_or("");
+                return ClientData::SetClientSeedRequest {
+                    client_seed: sanitize_client_seed(client_seed),
                 };
             }
         }
         RequestMessages::BetRequest => {
             if let Some(bet_data) = gameplay.msg_as_bet_request() {
                 let bet_amount = bet_data.bet_amount();
-                return ClientData::BetRequest { bet_amount };
+                // 0 (or anything not above 1.00x) means no auto crash out
+                let auto_crash_out_multiplier =
+                    Some(bet_data.auto_crash_out_multiplier()).filter(|m| *m > 100);
+                return ClientData::BetRequest {
+                    bet_amount,
+                    auto_crash_out_multiplier,
+                };
             }
         }
         RequestMessages::CrashOutRequest => {
             return ClientData::CrashOutRequest {};
         }
+        RequestMessages::RefillBalanceRequest => {
+            return ClientData::RefillBalanceRequest {};
+        }
         _ => {
             return ClientData::Unknown;
         }
@@ -40,13 +64,23 @@ pub fn parse_gameplay_data(buf: &[u8]) -> ClientData {
     ClientData::Unknown
 }
 
+fn sanitize_client_seed(client_seed: &str) -> String {
+    client_seed.trim().chars().take(MAX_CLIENT_SEED_LENGTH).collect()
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn create_join_game_response_success(
     game_state: u8,
     betting_time_left: u32,
     multiplier: u32,
     round_time_elapsed_ms: u32,
+    multiplier_growth_rate: f64,
     display_name: String,
     balance: u64,
+    recent_crash_points: Vec<u32>,
+    currency: String,
+    bet_limits: BetLimits,
+    room_id: String,
 ) -> Vec<u8> {
     let mut bldr = FlatBufferBuilder::new();
     let mut bytes: Vec<u8> = Vec::new();
@@ -61,6 +95,9 @@ pub fn create_join_game_response_success(
     // (Note how we call `bldr.create_string` to create the UTF-8 string
     // ergonomically.)
     let display_name_str = bldr.create_string(&display_name);
+    let recent_crash_points_vec = bldr.create_vector(&recent_crash_points);
+    let currency_str = bldr.create_string(&currency);
+    let room_id_str = bldr.create_string(&room_id);
 
     let msg = JoinGameResponse::create(
         &mut bldr,
@@ -69,8 +106,15 @@ pub fn create_join_game_response_success(
             betting_time_left: betting_time_left,
             multiplier: multiplier,
             round_time_elapsed: round_time_elapsed_ms,
+            multiplier_growth_rate,
             display_name: Option::from(display_name_str),
             balance: balance,
+            recent_crash_points: Option::from(recent_crash_points_vec),
+            currency: Option::from(currency_str),
+            min_bet: bet_limits.min_bet,
+            max_bet: bet_limits.max_bet,
+            max_win: bet_limits.max_win,
+            room_id: Option::from(room_id_str),
         },
     )
     .as_union_value();
@@ -96,17 +140,44 @@ pub fn create_join_game_response_success(
     bytes
 }
 
-pub fn create_bet_response(balance: u64) -> Vec<u8> {
+pub fn create_join_game_error_response(code: u8) -> Vec<u8> {
     let mut bldr = FlatBufferBuilder::new();
     let mut bytes: Vec<u8> = Vec::new();
 
     bytes.clear();
     bldr.reset();
 
+    let msg = JoinGameError::create(&mut bldr, &JoinGameErrorArgs { code }).as_union_value();
+
+    let args = GameResponseEventArgs {
+        msg_type: ResponseMessage::JoinGameError,
+        msg: Option::from(msg),
+    };
+
+    let user_offset = GameResponseEvent::create(&mut bldr, &args);
+    bldr.finish(user_offset, None);
+
+    // Copy the serialized FlatBuffers data to our own byte buffer.
+    let finished_data = bldr.finished_data();
+    bytes.extend_from_slice(finished_data);
+
+    bytes
+}
+
+pub fn create_bet_response(balance: u64, currency: String) -> Vec<u8> {
+    let mut bldr = FlatBufferBuilder::new();
+    let mut bytes: Vec<u8> = Vec::new();
+
+    bytes.clear();
+    bldr.reset();
+
+    let currency_str = bldr.create_string(&currency);
+
     let msg = BetResponse::create(
         &mut bldr,
         &BetResponseArgs {
             balance: balance,
+            currency: Option::from(currency_str),
         },
     )
     .as_union_value();
@@ -126,7 +197,36 @@ pub fn create_bet_response(balance: u64) -> Vec<u8> {
     bytes
 }
 
-pub fn create_crash_out_response(win_amount: u64, multiplier: u32, balance: u64) -> Vec<u8> {
+pub fn create_bet_error_response(code: u8) -> Vec<u8> {
+    let mut bldr = FlatBufferBuilder::new();
+    let mut bytes: Vec<u8> = Vec::new();
+
+    bytes.clear();
+    bldr.reset();
+
+    let msg = BetError::create(&mut bldr, &BetErrorArgs { code }).as_union_value();
+
+    let args = GameResponseEventArgs {
+        msg_type: ResponseMessage::BetError,
+        msg: Option::from(msg),
+    };
+
+    let user_offset = GameResponseEvent::create(&mut bldr, &args);
+    bldr.finish(user_offset, None);
+
+    // Copy the serialized FlatBuffers data to our own byte buffer.
+    let finished_data = bldr.finished_data();
+    bytes.extend_from_slice(finished_data);
+
+    bytes
+}
+
+pub fn create_crash_out_response(
+    win_amount: u64,
+    multiplier: u32,
+    balance: u64,
+    reason: u8,
+) -> Vec<u8> {
     let mut bldr = FlatBufferBuilder::new();
     let mut bytes: Vec<u8> = Vec::new();
 
@@ -139,6 +239,7 @@ pub fn create_crash_out_response(win_amount: u64, multiplier: u32, balance: u64)
             win_amount: win_amount,
             multiplier: multiplier,
             balance: balance,
+            reason,
         },
     )
     .as_union_value();
@@ -158,6 +259,30 @@ pub fn create_crash_out_response(win_amount: u64, multiplier: u32, balance: u64)
     bytes
 }
 
+pub fn create_crash_out_error_response(code: u8) -> Vec<u8> {
+    let mut bldr = FlatBufferBuilder::new();
+    let mut bytes: Vec<u8> = Vec::new();
+
+    bytes.clear();
+    bldr.reset();
+
+    let msg = CrashOutError::create(&mut bldr, &CrashOutErrorArgs { code }).as_union_value();
+
+    let args = GameResponseEventArgs {
+        msg_type: ResponseMessage::CrashOutError,
+        msg: Option::from(msg),
+    };
+
+    let user_offset = GameResponseEvent::create(&mut bldr, &args);
+    bldr.finish(user_offset, None);
+
+    // Copy the serialized FlatBuffers data to our own byte buffer.
+    let finished_data = bldr.finished_data();
+    bytes.extend_from_slice(finished_data);
+
+    bytes
+}
+
 pub fn create_game_update_response(multiplier: u32) -> Vec<u8> {
     let mut bldr = FlatBufferBuilder::new();
     let mut bytes: Vec<u8> = Vec::new();
@@ -270,7 +395,7 @@ pub fn create_betting_timer_started_response(
     betting_time_left: u32,
     round_id: u32,
     server_seed_hash: String,
-    next_round_server_seed_hash: String,
+    seed_chain_hash: String,
 ) -> Vec<u8> {
     let mut bldr = FlatBufferBuilder::new();
     let mut bytes: Vec<u8> = Vec::new();
@@ -279,7 +404,7 @@ pub fn create_betting_timer_started_response(
     bldr.reset();
 
     let server_seed_hash_str = bldr.create_string(&server_seed_hash);
-    let next_round_server_seed_hash_str = bldr.create_string(&next_round_server_seed_hash);
+    let seed_chain_hash_str = bldr.create_string(&seed_chain_hash);
 
     let msg = BettingTimerStarted::create(
         &mut bldr,
@@ -287,7 +412,7 @@ pub fn create_betting_timer_started_response(
             betting_time_left: betting_time_left,
             round_id,
             server_seed_hash: Option::from(server_seed_hash_str),
-            next_round_server_seed_hash: Option::from(next_round_server_seed_hash_str),
+            seed_chain_hash: Option::from(seed_chain_hash_str),
         },
     )
     .as_union_value();
@@ -438,3 +563,257 @@ pub fn create_remote_player_crash_out_response(display_name: String, win_amount:
 
     bytes
 }
+
+pub fn create_round_result_response(
+    round_id: u32,
+    crash_point: u32,
+    server_seed: String,
+    client_seed: String,
+    client_seed_inputs: Vec<String>,
+    client_seed_salt: String,
+    house_edge_pct: f32,
+) -> Vec<u8> {
+    let mut bldr = FlatBufferBuilder::new();
+    let mut bytes: Vec<u8> = Vec::new();
+
+    bytes.clear();
+    bldr.reset();
+
+    let server_seed_str = bldr.create_string(&server_seed);
+    let client_seed_str = bldr.create_string(&client_seed);
+    let client_seed_input_strs: Vec<WIPOffset<&str>> = client_seed_inputs
+        .iter()
+        .map(|seed| bldr.create_string(seed))
+        .collect();
+    let client_seed_inputs_vec = bldr.create_vector(&client_seed_input_strs);
+    let client_seed_salt_str = bldr.create_string(&client_seed_salt);
+
+    let msg = RoundResult::create(
+        &mut bldr,
+        &RoundResultArgs {
+            round_id,
+            crash_point,
+            server_seed: Option::from(server_seed_str),
+            client_seed: Option::from(client_seed_str),
+            client_seed_inputs: Option::from(client_seed_inputs_vec),
+            client_seed_salt: Option::from(client_seed_salt_str),
+            house_edge: house_edge_pct,
+        },
+    )
+    .as_union_value();
+
+    let args = GameResponseEventArgs {
+        msg_type: ResponseMessage::RoundResult,
+        msg: Option::from(msg),
+    };
+
+    let user_offset = GameResponseEvent::create(&mut bldr, &args);
+    bldr.finish(user_offset, None);
+
+    // Copy the serialized FlatBuffers data to our own byte buffer.
+    let finished_data = bldr.finished_data();
+    bytes.extend_from_slice(finished_data);
+
+    bytes
+}
+
+pub fn create_refill_balance_response(balance: u64) -> Vec<u8> {
+    let mut bldr = FlatBufferBuilder::new();
+    let mut bytes: Vec<u8> = Vec::new();
+
+    bytes.clear();
+    bldr.reset();
+
+    let msg = RefillBalanceResponse::create(&mut bldr, &RefillBalanceResponseArgs { balance })
+        .as_union_value();
+
+    let args = GameResponseEventArgs {
+        msg_type: ResponseMessage::RefillBalanceResponse,
+        msg: Option::from(msg),
+    };
+
+    let user_offset = GameResponseEvent::create(&mut bldr, &args);
+    bldr.finish(user_offset, None);
+
+    // Copy the serialized FlatBuffers data to our own byte buffer.
+    let finished_data = bldr.finished_data();
+    bytes.extend_from_slice(finished_data);
+
+    bytes
+}
+
+pub fn create_refill_balance_error_response(code: u8) -> Vec<u8> {
+    let mut bldr = FlatBufferBuilder::new();
+    let mut bytes: Vec<u8> = Vec::new();
+
+    bytes.clear();
+    bldr.reset();
+
+    let msg =
+        RefillBalanceError::create(&mut bldr, &RefillBalanceErrorArgs { code }).as_union_value();
+
+    let args = GameResponseEventArgs {
+        msg_type: ResponseMessage::RefillBalanceError,
+        msg: Option::from(msg),
+    };
+
+    let user_offset = GameResponseEvent::create(&mut bldr, &args);
+    bldr.finish(user_offset, None);
+
+    // Copy the serialized FlatBuffers data to our own byte buffer.
+    let finished_data = bldr.finished_data();
+    bytes.extend_from_slice(finished_data);
+
+    bytes
+}
+
+pub fn create_round_phase_changed_response(phase: u8, crash_point: u32) -> Vec<u8> {
+    let mut bldr = FlatBufferBuilder::new();
+    let mut bytes: Vec<u8> = Vec::new();
+
+    bytes.clear();
+    bldr.reset();
+
+    let msg = RoundPhaseChanged::create(&mut bldr, &RoundPhaseChangedArgs { phase, crash_point })
+        .as_union_value();
+
+    let args = GameResponseEventArgs {
+        msg_type: ResponseMessage::RoundPhaseChanged,
+        msg: Option::from(msg),
+    };
+
+    let user_offset = GameResponseEvent::create(&mut bldr, &args);
+    bldr.finish(user_offset, None);
+
+    // Copy the serialized FlatBuffers data to our own byte buffer.
+    let finished_data = bldr.finished_data();
+    bytes.extend_from_slice(finished_data);
+
+    bytes
+}
+
+pub fn create_round_summary_response(
+    round_id: u32,
+    crash_point: u32,
+    total_wagered: u64,
+    total_paid_out: u64,
+    player_count: u32,
+    winners: Vec<RoundWinner>,
+    cooldown_ms: u32,
+) -> Vec<u8> {
+    let mut bldr = FlatBufferBuilder::new();
+    let mut bytes: Vec<u8> = Vec::new();
+
+    bytes.clear();
+    bldr.reset();
+
+    let winner_offsets: Vec<WIPOffset<RoundWinnerData>> = winners
+        .iter()
+        .map(|winner| {
+            let display_name_str = bldr.create_string(&winner.display_name);
+            RoundWinnerData::create(
+                &mut bldr,
+                &RoundWinnerArgs {
+                    display_name: Option::from(display_name_str),
+                    bet_amount: winner.bet_amount,
+                    win_amount: winner.win_amount,
+                    multiplier: winner.multiplier,
+                },
+            )
+        })
+        .collect();
+    let winners_vec = bldr.create_vector(&winner_offsets);
+
+    let msg = RoundSummary::create(
+        &mut bldr,
+        &RoundSummaryArgs {
+            round_id,
+            crash_point,
+            total_wagered,
+            total_paid_out,
+            player_count,
+            winners: Option::from(winners_vec),
+            cooldown: cooldown_ms,
+        },
+    )
+    .as_union_value();
+
+    let args = GameResponseEventArgs {
+        msg_type: ResponseMessage::RoundSummary,
+        msg: Option::from(msg),
+    };
+
+    let user_offset = GameResponseEvent::create(&mut bldr, &args);
+    bldr.finish(user_offset, None);
+
+    // Copy the serialized FlatBuffers data to our own byte buffer.
+    let finished_data = bldr.finished_data();
+    bytes.extend_from_slice(finished_data);
+
+    bytes
+}
+
+pub fn create_waiting_for_players_response(
+    players_online: u32,
+    bets_placed: u32,
+    min_players: u32,
+    min_bets: u32,
+    betting_time_left_ms: u32,
+) -> Vec<u8> {
+    let mut bldr = FlatBufferBuilder::new();
+    let mut bytes: Vec<u8> = Vec::new();
+
+    bytes.clear();
+    bldr.reset();
+
+    let msg = WaitingForPlayers::create(
+        &mut bldr,
+        &WaitingForPlayersArgs {
+            players_online,
+            bets_placed,
+            min_players,
+            min_bets,
+            betting_time_left: betting_time_left_ms,
+        },
+    )
+    .as_union_value();
+
+    let args = GameResponseEventArgs {
+        msg_type: ResponseMessage::WaitingForPlayers,
+        msg: Option::from(msg),
+    };
+
+    let user_offset = GameResponseEvent::create(&mut bldr, &args);
+    bldr.finish(user_offset, None);
+
+    // Copy the serialized FlatBuffers data to our own byte buffer.
+    let finished_data = bldr.finished_data();
+    bytes.extend_from_slice(finished_data);
+
+    bytes
+}
+
+pub fn create_game_loop_state_changed_response(state: u8) -> Vec<u8> {
+    let mut bldr = FlatBufferBuilder::new();
+    let mut bytes: Vec<u8> = Vec::new();
+
+    bytes.clear();
+    bldr.reset();
+
+    let msg = GameLoopStateChanged::create(&mut bldr, &GameLoopStateChangedArgs { state })
+        .as_union_value();
+
+    let args = GameResponseEventArgs {
+        msg_type: ResponseMessage::GameLoopStateChanged,
+        msg: Option::from(msg),
+    };
+
+    let user_offset = GameResponseEvent::create(&mut bldr, &args);
+    bldr.finish(user_offset, None);
+
+    // Copy the serialized FlatBuffers data to our own byte buffer.
+    let finished_data = bldr.finished_data();
+    bytes.extend_from_slice(finished_data);
+
+    bytes
+}